@@ -1,16 +1,11 @@
+use crate::intcode::loader::parse_program;
 use crate::intcode::Intcode;
 
 pub fn run(input: String) {
-    let input = input.trim();
-    let input: Vec<&str> = input.split(",").collect();
-    let mut memory = Vec::new();
-    for value in input {
-        let num : i64 = value.parse().expect("Failed to parse usize for Day 2");
-        memory.push(num);
-    }
+    let memory = parse_program(&input).expect("Failed to parse Intcode program");
 
     let mut computer = Intcode::new(memory);
     println!("Start compute");
-    computer.compute();
+    computer.compute().expect("Intcode program faulted");
     println!("Day 5 Task1: {}", computer.result());
 }
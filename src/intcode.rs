@@ -1,201 +1,1123 @@
 #![allow(dead_code)]
 
-use std::ops::{Index, IndexMut};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::ops::{Index, IndexMut, Range};
 use std::io::stdin;
+use std::time::{Duration, Instant};
 
+pub mod amplifier;
+pub mod ascii;
+pub mod assembler;
+mod cache;
+pub mod cfg;
+pub mod debugger;
+pub mod disassembler;
+pub mod dump;
+mod error;
+pub mod extension;
+pub mod journal;
+pub mod loader;
+mod memory;
+pub mod network;
+pub mod profile;
+pub mod search;
+pub mod session;
+pub mod snapshot;
+pub mod symbolic;
+pub mod trace;
+
+use cache::DecodeCache;
+pub use error::IntcodeError;
+pub use memory::{Memory, ADDRESS_LIMIT};
+
+use extension::{Effect, Extension, Handler, Param};
+use journal::{Journal, JournalEntry};
+use profile::Profile;
+use snapshot::Snapshot;
+use trace::{Io, TraceRecord};
 
 type PC = usize;
 
-#[derive(Debug)]
-pub struct Intcode {
+/// A memory word. Words have room for 128 bits so any machine can switch to
+/// `Arithmetic::Wide`, the other policies keep results within 64 bits.
+pub type Word = i128;
+
+/// How many instructions run between clock reads when a time limit is set.
+const CLOCK_INTERVAL: u64 = 1024;
+
+/// Where opcode 3 takes its values from. `None` means no input is available.
+pub trait InputSource {
+    fn read(&mut self) -> Option<Word>;
+
+    /// Values held but not read yet, saved in snapshots. Sources that cannot
+    /// be saved, like the console, hold none.
+    fn queued(&self) -> Vec<Word> {
+        Vec::new()
+    }
+
+    fn restore_queued(&mut self, _values: &[Word]) {}
+}
+
+/// Where opcode 4 puts its values.
+pub trait OutputSink {
+    fn write(&mut self, value: Word);
+
+    /// Values collected so far, saved in snapshots. Sinks that pass values
+    /// on, like the console, hold none.
+    fn written(&self) -> Vec<Word> {
+        Vec::new()
+    }
+
+    fn restore_written(&mut self, _values: &[Word]) {}
+}
+
+/// Interactive input from stdin and output to stdout.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Console;
+
+impl InputSource for Console {
+    fn read(&mut self) -> Option<Word> {
+        let mut s = String::new();
+        loop {
+            println!("Input number: ");
+            s.clear();
+            if stdin().read_line(&mut s).expect("Invalid Input!") == 0 {
+                return None;
+            }
+            let trimmed = s.trim();
+            if let Ok(value) = trimmed.parse::<Word>() {
+                return Some(value);
+            } else {
+                println!("Couldn't parse number: '{}'", trimmed);
+            }
+        }
+    }
+}
+
+impl OutputSink for Console {
+    fn write(&mut self, value: Word) {
+        println!("Output: {}", value);
+    }
+}
+
+impl InputSource for VecDeque<Word> {
+    fn read(&mut self) -> Option<Word> {
+        self.pop_front()
+    }
+
+    fn queued(&self) -> Vec<Word> {
+        self.iter().cloned().collect()
+    }
+
+    fn restore_queued(&mut self, values: &[Word]) {
+        self.clear();
+        self.extend(values);
+    }
+}
+
+impl OutputSink for Vec<Word> {
+    fn write(&mut self, value: Word) {
+        self.push(value);
+    }
+
+    fn written(&self) -> Vec<Word> {
+        self.clone()
+    }
+
+    fn restore_written(&mut self, values: &[Word]) {
+        self.clear();
+        self.extend_from_slice(values);
+    }
+}
+
+/// A machine driven from code: input is queued, output is collected.
+pub type QueueIntcode = Intcode<VecDeque<Word>, Vec<Word>>;
+
+/// Why `step` or `run` handed control back to the caller.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    /// An instruction was executed and the machine can continue. Only returned by `step`.
+    Running,
+    /// Opcode 3 found neither fed nor source input. `pc` still points at it.
+    WaitingForInput,
+    Output(Word),
+    Halted,
+    /// A limit was hit after `executed` instructions, counted like the limit.
+    /// `pc` points at the next instruction and the machine stays stopped until
+    /// the limits change.
+    LimitReached { limit: Limit, executed: u64, pc: PC },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    Instructions,
+    Time,
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Limit::Instructions => write!(f, "instruction limit"),
+            Limit::Time => write!(f, "time limit"),
+        }
+    }
+}
+
+/// How wide the results of `ADD`, `MUL`, `ARB` and relative addresses are,
+/// and what happens when they overflow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Arithmetic {
+    /// 64-bit results, overflow faults with `IntcodeError::Overflow`.
+    #[default]
+    Checked,
+    /// 64-bit results that wrap around in two's complement.
+    Wrapping,
+    /// 128-bit results, overflow faults with `IntcodeError::Overflow`.
+    Wide,
+}
+
+/// Caps on how long a machine may run, counted from `set_limits` or the last
+/// `reset`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Limits {
+    pub instructions: Option<u64>,
+    pub time: Option<Duration>,
+}
+
+/// A memory write done by the last executed instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryWrite {
+    pub address: usize,
+    pub old: Word,
+    pub new: Word,
+}
+
+#[derive(Debug, Clone)]
+pub struct Intcode<I = Console, O = Console> {
     pc: PC,
-    memory: Vec<i64>,
-    original_memory: Vec<i64>,
+    memory: Memory,
+    original_memory: Vec<Word>,
+    relative_base: Word,
+    pending_input: VecDeque<Word>,
+    last_write: Option<MemoryWrite>,
+    trace: Option<Vec<TraceRecord>>,
+    traced_params: Vec<Word>,
+    journal: Option<Journal>,
+    profile: Option<Profile>,
+    cache: Option<DecodeCache>,
+    executed: u64,
+    /// Value of `executed` when the limits were set.
+    limit_base: u64,
+    limits: Limits,
+    deadline: Option<Instant>,
+    arithmetic: Arithmetic,
+    extensions: HashMap<PC, Extension<I, O>>,
+    source: I,
+    sink: O,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParameterMode {
+    Position,
+    Immediate,
+    Relative,
 }
 
-enum ParameterMode {
-    PositionMode,
-    ImmediateMode,
+/// Whether an instruction parameter is read from or written to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Read,
+    Write,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpcodeInfo {
+    pub opcode: PC,
+    pub mnemonic: &'static str,
+    pub params: &'static [Role],
+}
+
+pub const OPCODES: [OpcodeInfo; 10] = [
+    OpcodeInfo { opcode: 1, mnemonic: "ADD", params: &[Role::Read, Role::Read, Role::Write] },
+    OpcodeInfo { opcode: 2, mnemonic: "MUL", params: &[Role::Read, Role::Read, Role::Write] },
+    OpcodeInfo { opcode: 3, mnemonic: "IN", params: &[Role::Write] },
+    OpcodeInfo { opcode: 4, mnemonic: "OUT", params: &[Role::Read] },
+    OpcodeInfo { opcode: 5, mnemonic: "JT", params: &[Role::Read, Role::Read] },
+    OpcodeInfo { opcode: 6, mnemonic: "JF", params: &[Role::Read, Role::Read] },
+    OpcodeInfo { opcode: 7, mnemonic: "LT", params: &[Role::Read, Role::Read, Role::Write] },
+    OpcodeInfo { opcode: 8, mnemonic: "EQ", params: &[Role::Read, Role::Read, Role::Write] },
+    OpcodeInfo { opcode: 9, mnemonic: "ARB", params: &[Role::Read] },
+    OpcodeInfo { opcode: 99, mnemonic: "HLT", params: &[] },
+];
+
+pub fn opcode_info(opcode: PC) -> Option<&'static OpcodeInfo> {
+    OPCODES.iter().find(|info| info.opcode == opcode)
 }
 
 struct ModeIter {
-    num: i64,
+    num: Word,
 }
 
 impl ModeIter {
-    fn new(num: i64) -> Self {
+    fn new(num: Word) -> Self {
         ModeIter{num}
     }
 
-    fn next(&mut self) -> ParameterMode {
+    /// Returns the next mode, or the offending digit if it is not a valid mode.
+    fn next(&mut self) -> Result<ParameterMode, Word> {
         if self.num == 0 {
-            return ParameterMode::PositionMode;
+            return Ok(ParameterMode::Position);
         }
         let digit = self.num % 10;
         self.num /= 10;
         match digit {
-            0 => ParameterMode::PositionMode,
-            1 => ParameterMode::ImmediateMode,
-            _ => panic!("Invalid ParameterMode"),
+            0 => Ok(ParameterMode::Position),
+            1 => Ok(ParameterMode::Immediate),
+            2 => Ok(ParameterMode::Relative),
+            _ => Err(digit),
         }
     }
+
+    fn is_exhausted(&self) -> bool {
+        self.num == 0
+    }
+}
+
+/// An instruction with its parameter modes and raw operand words fetched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Instruction {
+    opcode: PC,
+    params: usize,
+    modes: [ParameterMode; 3],
+    operands: [Word; 3],
+}
+
+/// Splits an instruction word into its opcode and parameter modes.
+fn decode(value: Word) -> (PC, ModeIter) {
+    let opcode = value % 100;
+    let mode_digits = value / 100;
+    (opcode as usize, ModeIter::new(mode_digits))
 }
 
 impl Intcode {
-    pub fn new(memory: Vec<i64>) -> Self {
+    pub fn new(memory: Vec<Word>) -> Self {
+        Intcode::with_io(memory, Console, Console)
+    }
+}
+
+impl<I: InputSource, O: OutputSink> Intcode<I, O> {
+    pub fn with_io(memory: Vec<Word>, source: I, sink: O) -> Self {
         Intcode {
             pc: 0,
-            memory: memory.clone(),
-            original_memory: memory
+            memory: Memory::from(memory.as_slice()),
+            original_memory: memory,
+            relative_base: 0,
+            pending_input: VecDeque::new(),
+            last_write: None,
+            trace: None,
+            traced_params: Vec::new(),
+            journal: None,
+            profile: None,
+            cache: None,
+            executed: 0,
+            limit_base: 0,
+            limits: Limits::default(),
+            deadline: None,
+            arithmetic: Arithmetic::default(),
+            extensions: HashMap::new(),
+            source,
+            sink,
+        }
+    }
+
+    pub fn source(&self) -> &I {
+        &self.source
+    }
+
+    pub fn source_mut(&mut self) -> &mut I {
+        &mut self.source
+    }
+
+    pub fn sink(&self) -> &O {
+        &self.sink
+    }
+
+    pub fn sink_mut(&mut self) -> &mut O {
+        &mut self.sink
+    }
+
+    pub fn into_io(self) -> (I, O) {
+        (self.source, self.sink)
+    }
+
+    /// Runs until the program halts, passing output to the sink.
+    pub fn compute(&mut self) -> Result<(), IntcodeError> {
+        loop {
+            match self.run()? {
+                Status::Output(value) => self.sink.write(value),
+                Status::WaitingForInput => {
+                    return Err(IntcodeError::InputExhausted {
+                        pc: self.pc,
+                        instruction: self.memory[self.pc],
+                    })
+                }
+                Status::Halted => return Ok(()),
+                Status::LimitReached { limit, executed, .. } => return Err(self.limit_error(limit, executed)),
+                Status::Running => unreachable!(),
+            }
         }
     }
 
-    pub fn compute(&mut self) {
+    /// Runs until the program halts, produces output or waits for input.
+    pub fn run(&mut self) -> Result<Status, IntcodeError> {
         loop {
-            let (opcode, mode_iter) = self.get_opcode();
-            let inc = match opcode {
-                1 => self.add(mode_iter),
-                2 => self.mul(mode_iter),
-                3 => self.input(mode_iter),
-                4 => self.output(mode_iter),
-                5 => self.jump_if_true(mode_iter),
-                6 => self.jump_if_false(mode_iter),
-                7 => self.less_than(mode_iter),
-                8 => self.equals(mode_iter),
-                99 => break,
-                _ => panic!("Invalid opcode"),
+            match self.step()? {
+                Status::Running => continue,
+                status => return Ok(status),
+            }
+        }
+    }
+
+    /// Executes a single instruction.
+    pub fn step(&mut self) -> Result<Status, IntcodeError> {
+        if let Some(status) = self.check_limits() {
+            return Ok(status);
+        }
+        self.last_write = None;
+        let pc = self.pc;
+        let relative_base = self.relative_base;
+        let instruction = self.fetch()?;
+        let opcode = instruction.opcode;
+        self.traced_params.clear();
+        let status = self.execute(&instruction)?;
+        // Waiting for input and halting leave pc where it is, so they can repeat.
+        let retired = !matches!(status, Status::WaitingForInput | Status::Halted);
+        if retired {
+            self.executed += 1;
+        }
+        if let Some(profile) = &mut self.profile {
+            if retired {
+                profile.retired += 1;
+                *profile.opcodes.entry(opcode).or_insert(0) += 1;
+                *profile.pcs.entry(pc).or_insert(0) += 1;
+            }
+        }
+        if let Some(journal) = &mut self.journal {
+            if status == Status::Running || matches!(status, Status::Output(_)) {
+                let input = match self.last_write {
+                    Some(write) if opcode == 3 => Some(write.new),
+                    _ => None,
+                };
+                journal.push(JournalEntry {
+                    pc,
+                    relative_base,
+                    write: self.last_write,
+                    input,
+                });
+            }
+        }
+        if let Some(trace) = &mut self.trace {
+            let io = match status {
+                Status::Output(value) => Some(Io::Output(value)),
+                Status::WaitingForInput => return Ok(status),
+                _ if opcode == 3 => self.last_write.map(|write| Io::Input(write.new)),
+                _ => None,
             };
-            // println!("{:?}", memory);
-            self.pc += inc;
+            trace.push(TraceRecord {
+                pc,
+                opcode,
+                params: self.traced_params.clone(),
+                write: self.last_write,
+                io,
+            });
+        }
+        Ok(status)
+    }
+
+    fn check_limits(&self) -> Option<Status> {
+        let (executed, pc) = (self.executed - self.limit_base, self.pc);
+        if self.limits.instructions.is_some_and(|limit| executed >= limit) {
+            return Some(Status::LimitReached { limit: Limit::Instructions, executed, pc });
+        }
+        match self.deadline {
+            Some(deadline) if executed % CLOCK_INTERVAL == 0 && Instant::now() >= deadline => {
+                Some(Status::LimitReached { limit: Limit::Time, executed, pc })
+            }
+            _ => None,
+        }
+    }
+
+    /// The fault `compute` reports for a `Status::LimitReached`.
+    fn limit_error(&self, limit: Limit, executed: u64) -> IntcodeError {
+        IntcodeError::LimitReached {
+            pc: self.pc,
+            instruction: self.memory[self.pc],
+            limit,
+            executed,
+        }
+    }
+
+    /// Decodes the instruction at pc, or takes it from the decode cache.
+    fn fetch(&mut self) -> Result<Instruction, IntcodeError> {
+        if let Some(instruction) = self.cache.as_ref().and_then(|cache| cache.get(self.pc)) {
+            return Ok(instruction);
+        }
+        let (opcode, mut mode_iter) = self.get_opcode();
+        let params = opcode_info(opcode)
+            .or_else(|| self.extensions.get(&opcode).map(|extension| &extension.info))
+            .map_or(0, |info| info.params.len());
+        let mut instruction = Instruction {
+            opcode,
+            params,
+            modes: [ParameterMode::Position; 3],
+            operands: [0; 3],
+        };
+        for i in 0..params {
+            instruction.modes[i] = self.next_mode(&mut mode_iter)?;
+            instruction.operands[i] = self.memory[self.pc + 1 + i];
+        }
+        if let Some(cache) = &mut self.cache {
+            cache.insert(self.pc, instruction);
+        }
+        Ok(instruction)
+    }
+
+    fn execute(&mut self, instruction: &Instruction) -> Result<Status, IntcodeError> {
+        let inc = match instruction.opcode {
+            1 => self.add(instruction)?,
+            2 => self.mul(instruction)?,
+            3 => match self.input(instruction)? {
+                Some(inc) => inc,
+                None => return Ok(Status::WaitingForInput),
+            },
+            4 => {
+                let value = self.output(instruction)?;
+                self.pc += 2;
+                return Ok(Status::Output(value));
+            }
+            5 => self.jump_if_true(instruction)?,
+            6 => self.jump_if_false(instruction)?,
+            7 => self.less_than(instruction)?,
+            8 => self.equals(instruction)?,
+            9 => self.adjust_relative_base(instruction)?,
+            99 => return Ok(Status::Halted),
+            opcode => match self.extensions.get(&opcode) {
+                Some(extension) => {
+                    let (roles, handler) = (extension.info.params, extension.handler);
+                    return self.extension(instruction, roles, handler);
+                }
+                None => {
+                    return Err(IntcodeError::UnknownOpcode {
+                        pc: self.pc,
+                        instruction: self.memory[self.pc],
+                    })
+                }
+            },
+        };
+        self.pc += inc;
+        Ok(Status::Running)
+    }
+
+    /// Adds an opcode. `params` gives the role of each of its parameters, at
+    /// most three, and `handler` gets them resolved with the usual modes.
+    pub fn register_opcode(&mut self, opcode: PC, mnemonic: &'static str, params: &'static [Role], handler: Handler<I, O>) {
+        assert!(opcode_info(opcode).is_none(), "Opcode {} is built in", opcode);
+        assert!(opcode < 100, "Opcode {} does not fit in two digits", opcode);
+        assert!(params.len() <= 3, "Custom opcodes take at most 3 parameters");
+        let info = OpcodeInfo { opcode, mnemonic, params };
+        self.extensions.insert(opcode, Extension { info, handler });
+        if let Some(profile) = &mut self.profile {
+            profile.extensions.push(info);
+        }
+        if let Some(cache) = &mut self.cache {
+            cache.clear();
+        }
+    }
+
+    /// The registered custom opcodes.
+    pub fn extensions(&self) -> impl Iterator<Item = &OpcodeInfo> {
+        self.extensions.values().map(|extension| &extension.info)
+    }
+
+    /// Writes memory the way instructions do, for custom opcode handlers. An
+    /// instruction may store once, so the journal and trace can record it.
+    pub fn store(&mut self, address: usize, value: Word) -> Result<(), IntcodeError> {
+        if self.last_write.is_some() {
+            return Err(IntcodeError::SecondWrite {
+                pc: self.pc,
+                instruction: self.memory[self.pc],
+            });
+        }
+        self.write(address, value);
+        Ok(())
+    }
+
+    /// Queues a value for the next input instruction, ahead of the source.
+    pub fn feed(&mut self, value: Word) {
+        self.pending_input.push_back(value);
+    }
+
+    /// Whether pc points at a halt instruction.
+    pub fn is_halted(&self) -> bool {
+        self.get_opcode().0 == 99
+    }
+
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    pub fn set_pc(&mut self, pc: usize) {
+        self.pc = pc;
+    }
+
+    pub fn relative_base(&self) -> Word {
+        self.relative_base
+    }
+
+    pub fn set_relative_base(&mut self, relative_base: Word) {
+        self.relative_base = relative_base;
+    }
+
+    pub fn memory(&self) -> &Memory {
+        &self.memory
+    }
+
+    pub fn original_memory(&self) -> &[Word] {
+        &self.original_memory
+    }
+
+    /// Formatted memory rows with disassembly, see `dump::dump`.
+    pub fn dump(&self, range: Range<usize>, width: usize) -> String {
+        let extensions: Vec<OpcodeInfo> = self.extensions().copied().collect();
+        dump::dump(&self.memory, range, width, &extensions)
+    }
+
+    /// The words that differ from the loaded program.
+    pub fn memory_diff(&self) -> Vec<MemoryWrite> {
+        dump::diff(&self.original_memory, &self.memory)
+    }
+
+    pub fn last_write(&self) -> Option<MemoryWrite> {
+        self.last_write
+    }
+
+    /// Starts recording every executed instruction.
+    pub fn enable_trace(&mut self) {
+        if self.trace.is_none() {
+            self.trace = Some(Vec::new());
+        }
+    }
+
+    /// Stops recording and returns what was recorded.
+    pub fn disable_trace(&mut self) -> Vec<TraceRecord> {
+        self.trace.take().unwrap_or_default()
+    }
+
+    /// Returns the records so far and keeps recording.
+    pub fn take_trace(&mut self) -> Vec<TraceRecord> {
+        match &mut self.trace {
+            Some(trace) => std::mem::take(trace),
+            None => Vec::new(),
         }
     }
 
-    pub fn result(&self) -> i64 {
+    pub fn result(&self) -> Word {
         self.memory[0]
     }
 
+    /// Starts counting executed instructions and memory accesses.
+    pub fn enable_profile(&mut self) {
+        if self.profile.is_none() {
+            let mut profile = Profile::new();
+            profile.extensions = self.extensions().copied().collect();
+            self.profile = Some(profile);
+        }
+    }
+
+    /// Stops profiling and returns the counts collected so far.
+    pub fn disable_profile(&mut self) -> Profile {
+        self.profile.take().unwrap_or_default()
+    }
+
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_ref()
+    }
+
+    /// Keeps decoded instructions around so that loops and repeated runs
+    /// after `reset` skip decoding.
+    pub fn enable_decode_cache(&mut self) {
+        if self.cache.is_none() {
+            let mut cache = DecodeCache::new(self.original_memory.len());
+            cache.resync(|address| self.memory[address] != self.original_memory[address]);
+            self.cache = Some(cache);
+        }
+    }
+
+    pub fn disable_decode_cache(&mut self) {
+        self.cache = None;
+    }
+
+    /// Starts keeping an undo journal of up to `capacity` instructions.
+    pub fn enable_journal(&mut self, capacity: usize) {
+        self.journal = Some(Journal::new(capacity));
+    }
+
+    pub fn disable_journal(&mut self) {
+        self.journal = None;
+    }
+
+    pub fn journal(&self) -> Option<&Journal> {
+        self.journal.as_ref()
+    }
+
+    /// Undoes the most recently executed instruction. Returns what was undone,
+    /// or `None` if the journal is disabled or empty.
+    pub fn step_back(&mut self) -> Option<JournalEntry> {
+        let entry = self.journal.as_mut()?.pop()?;
+        self.pc = entry.pc;
+        self.relative_base = entry.relative_base;
+        if let Some(write) = entry.write {
+            self.memory[write.address] = write.old;
+            if let Some(cache) = &mut self.cache {
+                cache.invalidate(write.address);
+            }
+        }
+        if let Some(value) = entry.input {
+            self.pending_input.push_front(value);
+        }
+        self.last_write = None;
+        Some(entry)
+    }
+
+    /// Sets the limits and restarts counting instructions and time for them.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+        self.limit_base = self.executed;
+        self.deadline = limits.time.map(|time| Instant::now() + time);
+    }
+
+    pub fn limits(&self) -> Limits {
+        self.limits
+    }
+
+    /// Instructions executed since the machine was created or last reset.
+    /// Halting and waiting for input do not count.
+    pub fn executed(&self) -> u64 {
+        self.executed
+    }
+
+    pub fn set_arithmetic(&mut self, arithmetic: Arithmetic) {
+        self.arithmetic = arithmetic;
+    }
+
+    pub fn arithmetic(&self) -> Arithmetic {
+        self.arithmetic
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            pc: self.pc,
+            relative_base: self.relative_base,
+            memory: self.memory.clone(),
+            pending_input: self.pending_input.iter().cloned().collect(),
+            source: self.source.queued(),
+            sink: self.sink.written(),
+            executed: self.executed,
+        }
+    }
+
+    /// Limits count again from the restored state, as after `set_limits`.
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        self.pc = snapshot.pc;
+        self.relative_base = snapshot.relative_base;
+        self.memory = snapshot.memory.clone();
+        self.pending_input = snapshot.pending_input.iter().cloned().collect();
+        self.source.restore_queued(&snapshot.source);
+        self.sink.restore_written(&snapshot.sink);
+        self.executed = snapshot.executed;
+        self.limit_base = snapshot.executed;
+        self.deadline = self.limits.time.map(|time| Instant::now() + time);
+        self.last_write = None;
+        if let Some(journal) = &mut self.journal {
+            journal.clear();
+        }
+        if let Some(cache) = &mut self.cache {
+            let (memory, original) = (&self.memory, &self.original_memory);
+            cache.resync(|address| memory[address] != original[address]);
+        }
+    }
+
     pub fn reset(&mut self) {
-        self.memory = self.original_memory.clone();
+        if let Some(cache) = &mut self.cache {
+            cache.reset();
+        }
+        self.memory = Memory::from(self.original_memory.as_slice());
         self.pc = 0;
+        self.relative_base = 0;
+        self.pending_input.clear();
+        self.last_write = None;
+        if let Some(journal) = &mut self.journal {
+            journal.clear();
+        }
+        self.executed = 0;
+        self.limit_base = 0;
+        self.deadline = self.limits.time.map(|time| Instant::now() + time);
+    }
+
+    fn extension(&mut self, instruction: &Instruction, roles: &[Role], handler: Handler<I, O>) -> Result<Status, IntcodeError> {
+        let mut params = Vec::with_capacity(roles.len());
+        for (index, role) in roles.iter().enumerate() {
+            params.push(match role {
+                Role::Read => Param::Value(self.get_parameter_value(instruction, index)?),
+                Role::Write => Param::Address(self.get_write_address(instruction, index)?),
+            });
+        }
+        let next = self.pc + 1 + roles.len();
+        match handler(self, &params)? {
+            Effect::Continue => self.pc = next,
+            Effect::Jump(pc) => self.pc = pc,
+            Effect::Output(value) => {
+                self.pc = next;
+                return Ok(Status::Output(value));
+            }
+        }
+        Ok(Status::Running)
     }
 
     fn get_opcode(&self) -> (PC, ModeIter) {
-        let value = self.memory[self.pc];
-        let opcode = value % 100;
-        let mode_digits = value / 100;
-        (opcode as usize, ModeIter::new(mode_digits))
+        decode(self.memory[self.pc])
     }
 
-    fn get_parameter_value(&self, parameter_address: usize, parameter_mode: &mut ModeIter) -> i64 {
-        let parameter_value = self.memory[parameter_address];
-        match parameter_mode.next() {
-            ParameterMode::PositionMode => self.memory[parameter_value as usize],
-            ParameterMode::ImmediateMode => parameter_value,
+    fn read(&mut self, address: usize) -> Word {
+        if let Some(profile) = &mut self.profile {
+            *profile.reads.entry(address).or_insert(0) += 1;
         }
+        self.memory[address]
     }
-    
-    fn jump_if_true(&mut self, mut mode_iter: ModeIter) -> usize {
+
+    fn write(&mut self, address: usize, value: Word) {
+        if let Some(profile) = &mut self.profile {
+            *profile.writes.entry(address).or_insert(0) += 1;
+        }
+        if let Some(cache) = &mut self.cache {
+            cache.invalidate(address);
+        }
+        let old = self.memory[address];
+        self.memory[address] = value;
+        self.last_write = Some(MemoryWrite { address, old, new: value });
+    }
+
+    /// Checks that a value computed by the program is a usable address.
+    fn to_address(&self, address: Word) -> Result<usize, IntcodeError> {
         let pc = self.pc;
-        let param1 = self.get_parameter_value(pc + 1, &mut mode_iter);
-        let param2 = self.get_parameter_value(pc + 2, &mut mode_iter);
+        let instruction = self.memory[pc];
+        if address < 0 {
+            Err(IntcodeError::NegativeAddress { pc, instruction, address })
+        } else if address >= ADDRESS_LIMIT as Word {
+            Err(IntcodeError::AddressOutOfRange { pc, instruction, address })
+        } else {
+            Ok(address as usize)
+        }
+    }
+
+    fn checked_add(&self, a: Word, b: Word) -> Result<Word, IntcodeError> {
+        match self.arithmetic {
+            Arithmetic::Checked => self.narrow(a.checked_add(b)),
+            Arithmetic::Wrapping => Ok((a as i64).wrapping_add(b as i64) as Word),
+            Arithmetic::Wide => a.checked_add(b).ok_or_else(|| self.overflow()),
+        }
+    }
+
+    fn checked_mul(&self, a: Word, b: Word) -> Result<Word, IntcodeError> {
+        match self.arithmetic {
+            Arithmetic::Checked => self.narrow(a.checked_mul(b)),
+            Arithmetic::Wrapping => Ok((a as i64).wrapping_mul(b as i64) as Word),
+            Arithmetic::Wide => a.checked_mul(b).ok_or_else(|| self.overflow()),
+        }
+    }
+
+    /// The result if it fits 64 bits, an overflow fault otherwise.
+    fn narrow(&self, result: Option<Word>) -> Result<Word, IntcodeError> {
+        let range = i64::MIN as Word..=i64::MAX as Word;
+        result.filter(|value| range.contains(value)).ok_or_else(|| self.overflow())
+    }
+
+    fn overflow(&self) -> IntcodeError {
+        IntcodeError::Overflow {
+            pc: self.pc,
+            instruction: self.memory[self.pc],
+        }
+    }
+
+    fn next_mode(&self, mode_iter: &mut ModeIter) -> Result<ParameterMode, IntcodeError> {
+        mode_iter.next().map_err(|mode| IntcodeError::InvalidParameterMode {
+            pc: self.pc,
+            instruction: self.memory[self.pc],
+            mode,
+        })
+    }
+
+    fn get_parameter_value(&mut self, instruction: &Instruction, index: usize) -> Result<Word, IntcodeError> {
+        let parameter_value = instruction.operands[index];
+        let value = match instruction.modes[index] {
+            ParameterMode::Position => {
+                let address = self.to_address(parameter_value)?;
+                self.read(address)
+            }
+            ParameterMode::Immediate => parameter_value,
+            ParameterMode::Relative => {
+                let address = self.to_address(self.checked_add(self.relative_base, parameter_value)?)?;
+                self.read(address)
+            }
+        };
+        if self.trace.is_some() {
+            self.traced_params.push(value);
+        }
+        Ok(value)
+    }
+
+    fn get_write_address(&mut self, instruction: &Instruction, index: usize) -> Result<usize, IntcodeError> {
+        let parameter_value = instruction.operands[index];
+        let address = match instruction.modes[index] {
+            ParameterMode::Position => self.to_address(parameter_value)?,
+            ParameterMode::Immediate => {
+                return Err(IntcodeError::ImmediateWrite {
+                    pc: self.pc,
+                    instruction: self.memory[self.pc],
+                })
+            }
+            ParameterMode::Relative => self.to_address(self.checked_add(self.relative_base, parameter_value)?)?,
+        };
+        if self.trace.is_some() {
+            self.traced_params.push(address as Word);
+        }
+        Ok(address)
+    }
+    
+    fn jump_if_true(&mut self, instruction: &Instruction) -> Result<usize, IntcodeError> {
+        let param1 = self.get_parameter_value(instruction, 0)?;
+        let param2 = self.get_parameter_value(instruction, 1)?;
         if param1 != 0 {
-            self.pc = param2 as usize;
-            return 0;
+            self.pc = self.to_address(param2)?;
+            return Ok(0);
         }
-        3
+        Ok(3)
     }
 
-    fn jump_if_false(&mut self, mut mode_iter: ModeIter) -> usize {
-        let pc = self.pc;
-        let param1 = self.get_parameter_value(pc + 1, &mut mode_iter);
-        let param2 = self.get_parameter_value(pc + 2, &mut mode_iter);
+    fn jump_if_false(&mut self, instruction: &Instruction) -> Result<usize, IntcodeError> {
+        let param1 = self.get_parameter_value(instruction, 0)?;
+        let param2 = self.get_parameter_value(instruction, 1)?;
         if param1 == 0 {
-            self.pc = param2 as usize;
-            return 0;
+            self.pc = self.to_address(param2)?;
+            return Ok(0);
         }
-        3
+        Ok(3)
     }
     
-    fn less_than(&mut self, mut mode_iter: ModeIter) -> usize {
-        let pc = self.pc;
-        let param1 = self.get_parameter_value(pc + 1, &mut mode_iter);
-        let param2 = self.get_parameter_value(pc + 2, &mut mode_iter);
-        let out = self.memory[pc + 3];
+    fn less_than(&mut self, instruction: &Instruction) -> Result<usize, IntcodeError> {
+        let param1 = self.get_parameter_value(instruction, 0)?;
+        let param2 = self.get_parameter_value(instruction, 1)?;
+        let out = self.get_write_address(instruction, 2)?;
         if param1 < param2 {
-            self.memory[out as usize] = 1;
+            self.write(out, 1);
         } else {
-            self.memory[out as usize] = 0;
+            self.write(out, 0);
         }
-        4
+        Ok(4)
     }
 
-    fn equals(&mut self, mut mode_iter: ModeIter) -> usize {
-        let pc = self.pc;
-        let param1 = self.get_parameter_value(pc + 1, &mut mode_iter);
-        let param2 = self.get_parameter_value(pc + 2, &mut mode_iter);
-        let out = self.memory[pc + 3];
+    fn equals(&mut self, instruction: &Instruction) -> Result<usize, IntcodeError> {
+        let param1 = self.get_parameter_value(instruction, 0)?;
+        let param2 = self.get_parameter_value(instruction, 1)?;
+        let out = self.get_write_address(instruction, 2)?;
         if param1 == param2 {
-            self.memory[out as usize] = 1;
+            self.write(out, 1);
         } else {
-            self.memory[out as usize] = 0;
+            self.write(out, 0);
         }
-        4
+        Ok(4)
     }
     
-    fn input(&mut self, _mode_iter: ModeIter) -> usize {
-        let mut s = String::new();
-        let v;
-        let out = self.memory[self.pc + 1];
-        loop {
-            println!("Input number: ");
-            stdin().read_line(&mut s).expect("Invalid Input!");
-            let trimmed = s.trim();
-            if let Ok(value) = trimmed.parse::<i64>() {
-                v = value;
-                break;
-            } else {
-                println!("Couldn't parse number: '{}'", s);
-            }
-        }
-        self.memory[out as usize] = v;
-        2
+    fn input(&mut self, instruction: &Instruction) -> Result<Option<usize>, IntcodeError> {
+        let out = self.get_write_address(instruction, 0)?;
+        let v = match self.pending_input.pop_front() {
+            Some(value) => value,
+            None => match self.source.read() {
+                Some(value) => value,
+                None => return Ok(None),
+            },
+        };
+        self.write(out, v);
+        Ok(Some(2))
     }
 
-    fn output(&mut self, mut mode_iter: ModeIter) -> usize {
-        let param = self.get_parameter_value(self.pc + 1, &mut mode_iter);
-        println!("Output: {}", param);
-        2
+    fn output(&mut self, instruction: &Instruction) -> Result<Word, IntcodeError> {
+        self.get_parameter_value(instruction, 0)
     }
 
-    fn add(&mut self, mut mode_iter: ModeIter) -> usize {
-        let pc = self.pc;
-        let a = self.get_parameter_value(pc + 1, &mut mode_iter);
-        let b = self.get_parameter_value(pc + 2, &mut mode_iter);
-        let res = self.memory[pc + 3];
-        self.memory[res as usize] = a + b;
-        4
+    fn add(&mut self, instruction: &Instruction) -> Result<usize, IntcodeError> {
+        let a = self.get_parameter_value(instruction, 0)?;
+        let b = self.get_parameter_value(instruction, 1)?;
+        let res = self.get_write_address(instruction, 2)?;
+        let sum = self.checked_add(a, b)?;
+        self.write(res, sum);
+        Ok(4)
     }
 
-    fn mul(&mut self, mut mode_iter: ModeIter) -> usize {
-        let pc = self.pc;
-        let a = self.get_parameter_value(pc + 1, &mut mode_iter);
-        let b = self.get_parameter_value(pc + 2, &mut mode_iter);
-        let res = self.memory[pc + 3];
-        self.memory[res as usize] = a * b;
-        4
+    fn mul(&mut self, instruction: &Instruction) -> Result<usize, IntcodeError> {
+        let a = self.get_parameter_value(instruction, 0)?;
+        let b = self.get_parameter_value(instruction, 1)?;
+        let res = self.get_write_address(instruction, 2)?;
+        let product = self.checked_mul(a, b)?;
+        self.write(res, product);
+        Ok(4)
+    }
+
+    fn adjust_relative_base(&mut self, instruction: &Instruction) -> Result<usize, IntcodeError> {
+        let param = self.get_parameter_value(instruction, 0)?;
+        self.relative_base = self.checked_add(self.relative_base, param)?;
+        Ok(2)
     }
 }
 
-impl Index<usize> for Intcode {
-    type Output = i64;
+impl<I, O> Index<usize> for Intcode<I, O> {
+    type Output = Word;
 
     fn index(&self, index: usize) -> &Self::Output {
         &self.memory[index]
     }
 }
 
-impl IndexMut<usize> for Intcode {
+impl<I, O> IndexMut<usize> for Intcode<I, O> {
     fn index_mut(&mut self, index: usize) -> &mut Self::Output {
+        if let Some(cache) = &mut self.cache {
+            cache.invalidate(index);
+        }
         &mut self.memory[index]
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn restore_brings_back_queued_io() {
+        // Reads two values and writes each back.
+        let program = vec![3, 20, 4, 20, 3, 20, 4, 20, 99];
+        let mut machine = QueueIntcode::with_io(program, VecDeque::from(vec![1, 2]), Vec::new());
+        let snapshot = machine.snapshot();
+        assert_eq!(machine.run(), Ok(Status::Output(1)));
+        machine.restore(&snapshot);
+        assert_eq!(machine.executed(), 0);
+        assert_eq!(machine.run(), Ok(Status::Output(1)));
+        assert_eq!(machine.run(), Ok(Status::Output(2)));
+
+        let snapshot: Snapshot = snapshot.to_string().parse().unwrap();
+        assert_eq!(snapshot.source, [1, 2]);
+    }
+
+    #[test]
+    fn step_back_across_input_returns_the_value() {
+        let program = vec![3, 9, 1, 9, 9, 9, 4, 9, 99, 0];
+        let mut machine = QueueIntcode::with_io(program, VecDeque::from(vec![5]), Vec::new());
+        machine.enable_journal(16);
+        assert_eq!(machine.run(), Ok(Status::Output(10)));
+        machine.step_back();
+        machine.step_back();
+        assert_eq!(machine[9], 5);
+        machine.step_back();
+        assert_eq!((machine.pc(), machine[9]), (0, 0));
+        assert_eq!(machine.run(), Ok(Status::Output(10)));
+    }
+
+    #[test]
+    fn decode_cache_sees_patched_operand() {
+        // OUT [11], then patch its operand to 12 and jump back.
+        let program = vec![4, 11, 1101, 12, 0, 1, 1105, 1, 0, 99, 0, 7, 8];
+        let mut machine = QueueIntcode::with_io(program, VecDeque::new(), Vec::new());
+        machine.enable_decode_cache();
+        assert_eq!(machine.run(), Ok(Status::Output(7)));
+        assert_eq!(machine.run(), Ok(Status::Output(8)));
+        machine.reset();
+        assert_eq!(machine.run(), Ok(Status::Output(7)));
+    }
+
+    #[test]
+    fn restore_restarts_the_limits() {
+        // Counts up forever.
+        let program = vec![1001, 5, 1, 5, 1105, 1, 0];
+        let mut machine = QueueIntcode::with_io(program, VecDeque::new(), Vec::new());
+        let snapshot = machine.snapshot();
+        for _ in 0..100 {
+            machine.step().unwrap();
+        }
+        machine.set_limits(Limits { instructions: Some(10), time: None });
+        machine.restore(&snapshot);
+        let limit = Status::LimitReached { limit: Limit::Instructions, executed: 10, pc: 0 };
+        assert_eq!(machine.run(), Ok(limit));
+        assert_eq!(machine.executed(), 10);
+    }
+
+    #[test]
+    fn limits_count_from_set_limits_and_skip_halts() {
+        let mut machine = QueueIntcode::with_io(vec![99], VecDeque::new(), Vec::new());
+        machine.set_limits(Limits { instructions: Some(2), time: None });
+        for _ in 0..3 {
+            assert_eq!(machine.step(), Ok(Status::Halted));
+        }
+        assert_eq!(machine.executed(), 0);
+
+        // Three additions, then output.
+        let program = vec![1101, 1, 1, 0, 1101, 1, 1, 0, 1101, 1, 1, 0, 4, 0, 99];
+        let mut machine = QueueIntcode::with_io(program, VecDeque::new(), Vec::new());
+        machine.step().unwrap();
+        machine.step().unwrap();
+        machine.set_limits(Limits { instructions: Some(2), time: None });
+        assert_eq!(machine.step(), Ok(Status::Running));
+        assert_eq!(machine.step(), Ok(Status::Output(2)));
+        let limit = Status::LimitReached { limit: Limit::Instructions, executed: 2, pc: 14 };
+        assert_eq!(machine.step(), Ok(limit));
+    }
+
+    #[test]
+    fn custom_opcode_may_store_once() {
+        fn set(machine: &mut QueueIntcode, params: &[Param]) -> Result<Effect, IntcodeError> {
+            machine.store(params[0].get() as usize, 7)?;
+            Ok(Effect::Continue)
+        }
+        fn set_twice(machine: &mut QueueIntcode, params: &[Param]) -> Result<Effect, IntcodeError> {
+            set(machine, params)?;
+            machine.store(params[0].get() as usize + 1, 8)?;
+            Ok(Effect::Continue)
+        }
+        let mut machine = QueueIntcode::with_io(vec![42, 10, 43, 10, 99], VecDeque::new(), Vec::new());
+        machine.register_opcode(42, "SET", &[Role::Write], set);
+        machine.register_opcode(43, "SET2", &[Role::Write], set_twice);
+        machine.enable_profile();
+        assert_eq!(machine.step(), Ok(Status::Running));
+        assert_eq!(machine.step(), Err(IntcodeError::SecondWrite { pc: 2, instruction: 43 }));
+        assert!(machine.dump(0..4, 4).contains("0000: SET -> [10]  0002: SET2 -> [10]"));
+        assert!(machine.disable_profile().report(1).contains("SET "));
+    }
+
+    #[test]
+    fn arithmetic_policy_sets_the_width() {
+        // 2^32 * 2^32, one past the 64-bit range.
+        let program = vec![1102, 1 << 32, 1 << 32, 0, 4, 0, 99];
+        let run = |arithmetic| {
+            let mut machine = QueueIntcode::with_io(program.clone(), VecDeque::new(), Vec::new());
+            machine.set_arithmetic(arithmetic);
+            machine.run()
+        };
+        assert_eq!(run(Arithmetic::Checked), Err(IntcodeError::Overflow { pc: 0, instruction: 1102 }));
+        assert_eq!(run(Arithmetic::Wrapping), Ok(Status::Output(0)));
+        assert_eq!(run(Arithmetic::Wide), Ok(Status::Output(1 << 64)));
+
+        let program = vec![1101, i64::MAX as Word, 1, 0, 4, 0, 99];
+        let mut machine = QueueIntcode::with_io(program, VecDeque::new(), Vec::new());
+        machine.set_arithmetic(Arithmetic::Wrapping);
+        assert_eq!(machine.run(), Ok(Status::Output(i64::MIN as Word)));
+    }
+}
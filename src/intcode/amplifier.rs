@@ -0,0 +1,146 @@
+use std::error::Error;
+use std::fmt;
+
+use super::{IntcodeError, QueueIntcode, Status, Word};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AmplifierError {
+    Fault { amplifier: usize, error: IntcodeError },
+    /// The last amplifier never produced a signal.
+    NoOutput,
+    /// A feedback loop where no amplifier produced output but not all halted.
+    Stalled,
+}
+
+impl fmt::Display for AmplifierError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AmplifierError::Fault { amplifier, error } => write!(f, "amplifier {}: {}", amplifier, error),
+            AmplifierError::NoOutput => write!(f, "last amplifier produced no output"),
+            AmplifierError::Stalled => write!(f, "amplifiers wait for input that never comes"),
+        }
+    }
+}
+
+impl Error for AmplifierError {}
+
+/// Copies of one program wired in series, the output of every machine is the
+/// input of the next.
+pub struct AmplifierChain {
+    machines: Vec<QueueIntcode>,
+}
+
+impl AmplifierChain {
+    /// One amplifier per phase setting, each primed with its phase as first input.
+    pub fn new(program: &[Word], phases: &[Word]) -> Self {
+        let machines = phases
+            .iter()
+            .map(|phase| {
+                let mut machine = QueueIntcode::with_io(program.to_vec(), Default::default(), Vec::new());
+                machine.feed(*phase);
+                machine
+            })
+            .collect();
+        AmplifierChain { machines }
+    }
+
+    /// Passes `input` through every amplifier once.
+    pub fn run(&mut self, input: Word) -> Result<Word, AmplifierError> {
+        let signals = self.round(vec![input])?;
+        signals.last().copied().ok_or(AmplifierError::NoOutput)
+    }
+
+    /// Feeds the output of the last amplifier back into the first until every
+    /// amplifier halted, and returns the last signal sent to the thrusters.
+    pub fn run_feedback(&mut self, input: Word) -> Result<Word, AmplifierError> {
+        let mut signals = vec![input];
+        let mut thrusters = None;
+        loop {
+            signals = self.round(signals)?;
+            if let Some(signal) = signals.last() {
+                thrusters = Some(*signal);
+            }
+            if self.machines.iter().all(|machine| machine.is_halted()) {
+                return thrusters.ok_or(AmplifierError::NoOutput);
+            }
+            if signals.is_empty() {
+                return Err(AmplifierError::Stalled);
+            }
+        }
+    }
+
+    /// Gives every amplifier in turn the signals of the previous one and runs
+    /// it until it waits for more input or halts.
+    fn round(&mut self, mut signals: Vec<Word>) -> Result<Vec<Word>, AmplifierError> {
+        for (amplifier, machine) in self.machines.iter_mut().enumerate() {
+            for signal in signals.drain(..) {
+                machine.feed(signal);
+            }
+            loop {
+                match machine.run() {
+                    Ok(Status::Output(value)) => signals.push(value),
+                    Ok(Status::LimitReached { limit, executed, .. }) => {
+                        let error = machine.limit_error(limit, executed);
+                        return Err(AmplifierError::Fault { amplifier, error });
+                    }
+                    Ok(_) => break,
+                    Err(error) => return Err(AmplifierError::Fault { amplifier, error }),
+                }
+            }
+        }
+        Ok(signals)
+    }
+}
+
+/// Tries every ordering of `phases` and returns the highest thruster signal
+/// together with the phase order producing it.
+pub fn max_thruster_signal(program: &[Word], phases: &[Word], feedback: bool) -> Result<(Word, Vec<Word>), AmplifierError> {
+    let mut best: Option<(Word, Vec<Word>)> = None;
+    for order in permutations(phases) {
+        let mut chain = AmplifierChain::new(program, &order);
+        let signal = if feedback { chain.run_feedback(0)? } else { chain.run(0)? };
+        if best.as_ref().is_none_or(|(max, _)| signal > *max) {
+            best = Some((signal, order));
+        }
+    }
+    best.ok_or(AmplifierError::NoOutput)
+}
+
+pub fn permutations(items: &[Word]) -> Vec<Vec<Word>> {
+    if items.is_empty() {
+        return vec![Vec::new()];
+    }
+    let mut result = Vec::new();
+    for i in 0..items.len() {
+        let mut rest = items.to_vec();
+        let first = rest.remove(i);
+        for mut permutation in permutations(&rest) {
+            permutation.insert(0, first);
+            result.push(permutation);
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn series_example() {
+        let program = [3, 15, 3, 16, 1002, 16, 10, 16, 1, 16, 15, 15, 4, 15, 99, 0, 0];
+        let best = max_thruster_signal(&program, &[0, 1, 2, 3, 4], false).unwrap();
+        assert_eq!(best, (43210, vec![4, 3, 2, 1, 0]));
+    }
+
+    #[test]
+    fn feedback_example() {
+        let program = [
+            3, 26, 1001, 26, -4, 26, 3, 27, 1002, 27, 2, 27, 1, 27, 26, 27, 4, 27, 1001, 28, -1, 28, 1005, 28, 6,
+            99, 0, 0, 5,
+        ];
+        let best = max_thruster_signal(&program, &[5, 6, 7, 8, 9], true).unwrap();
+        assert_eq!(best, (139629729, vec![9, 8, 7, 6, 5]));
+        assert_eq!(AmplifierChain::new(&program, &[9, 8, 7, 6, 5]).run_feedback(0), Ok(139629729));
+    }
+}
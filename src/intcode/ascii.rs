@@ -0,0 +1,86 @@
+use std::collections::VecDeque;
+use std::io::{stdin, stdout, Write};
+
+use super::{InputSource, OutputSink, Word};
+
+fn is_ascii(value: Word) -> bool {
+    (0..128).contains(&value)
+}
+
+/// The character codes of `line` followed by a newline.
+pub fn encode_line(line: &str) -> Vec<Word> {
+    line.trim_end_matches(['\r', '\n'])
+        .chars()
+        .map(|c| c as Word)
+        .chain(std::iter::once(10))
+        .collect()
+}
+
+/// Values below 128 become text, anything else is written as a number on its
+/// own line.
+pub fn render(values: &[Word]) -> String {
+    let mut text = String::new();
+    for value in values {
+        if is_ascii(*value) {
+            text.push(*value as u8 as char);
+        } else {
+            if !text.is_empty() && !text.ends_with('\n') {
+                text.push('\n');
+            }
+            text.push_str(&format!("{}\n", value));
+        }
+    }
+    text
+}
+
+/// Interactive text terminal: program output is printed as text, and every
+/// line typed on stdin is fed to the program one character at a time.
+#[derive(Debug, Clone, Default)]
+pub struct AsciiConsole {
+    queue: VecDeque<Word>,
+    column: usize,
+}
+
+impl AsciiConsole {
+    pub fn new() -> Self {
+        AsciiConsole::default()
+    }
+}
+
+impl InputSource for AsciiConsole {
+    fn read(&mut self) -> Option<Word> {
+        if self.queue.is_empty() {
+            stdout().flush().expect("Failed to flush stdout");
+            let mut line = String::new();
+            if stdin().read_line(&mut line).expect("Invalid Input!") == 0 {
+                return None;
+            }
+            self.queue.extend(encode_line(&line));
+        }
+        self.queue.pop_front()
+    }
+
+    fn queued(&self) -> Vec<Word> {
+        self.queue.iter().cloned().collect()
+    }
+
+    fn restore_queued(&mut self, values: &[Word]) {
+        self.queue.clear();
+        self.queue.extend(values);
+    }
+}
+
+impl OutputSink for AsciiConsole {
+    fn write(&mut self, value: Word) {
+        if is_ascii(value) {
+            print!("{}", value as u8 as char);
+            self.column = if value == 10 { 0 } else { self.column + 1 };
+        } else {
+            if self.column != 0 {
+                println!();
+                self.column = 0;
+            }
+            println!("{}", value);
+        }
+    }
+}
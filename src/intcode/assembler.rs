@@ -0,0 +1,257 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+use super::{OpcodeInfo, ParameterMode, Role, Word, OPCODES};
+
+/// Assembles the mnemonic language into a program for `Intcode::new`.
+///
+/// One statement per line, `;` starts a comment:
+///
+/// ```text
+/// start:  in -> [x]
+///         mul [x], #2 -> [rb+1]
+///         jt #1, #start
+/// x:      data 0
+/// ```
+///
+/// Operands are `#n` (immediate), `[n]` (position) or `[rb+n]` (relative),
+/// where `n` is a number, a label or a label with an offset. A leading numeric
+/// `0010:` is an address annotation as printed by the disassembler and has to
+/// match the current address.
+pub fn assemble(source: &str) -> Result<Vec<Word>, AssembleError> {
+    let mut labels = HashMap::new();
+    let mut statements = Vec::new();
+    let mut address = 0;
+    for (number, text) in source.lines().enumerate() {
+        let line = number + 1;
+        let error = |message: String| AssembleError { line, message };
+        let mut rest = text.split(';').next().unwrap_or("").trim();
+        while let Some(colon) = rest.find(':') {
+            let name = rest[..colon].trim();
+            if name.is_empty() || name.contains(char::is_whitespace) {
+                break;
+            }
+            if name.chars().all(|c| c.is_ascii_digit()) {
+                let annotated: usize = name.parse().map_err(|_| error(format!("Invalid address '{}'", name)))?;
+                if annotated != address {
+                    return Err(error(format!("Address {} does not match actual address {}", annotated, address)));
+                }
+            } else if is_identifier(name) {
+                if labels.insert(name.to_string(), address as Word).is_some() {
+                    return Err(error(format!("Duplicate label '{}'", name)));
+                }
+            } else {
+                return Err(error(format!("Invalid label '{}'", name)));
+            }
+            rest = rest[colon + 1..].trim();
+        }
+        if rest.is_empty() {
+            continue;
+        }
+        let statement = parse_statement(rest).map_err(error)?;
+        address += statement.width();
+        statements.push((line, statement));
+    }
+
+    let mut program = Vec::with_capacity(address);
+    for (line, statement) in statements {
+        let resolve = |expr: &Expr| {
+            expr.resolve(&labels).map_err(|message| AssembleError { line, message })
+        };
+        match statement {
+            Statement::Data(values) => {
+                for value in &values {
+                    program.push(resolve(value)?);
+                }
+            }
+            Statement::Instruction(info, operands) => {
+                let mut instruction = info.opcode as Word;
+                let mut factor = 100;
+                for (mode, _) in &operands {
+                    instruction += factor * match mode {
+                        ParameterMode::Position => 0,
+                        ParameterMode::Immediate => 1,
+                        ParameterMode::Relative => 2,
+                    };
+                    factor *= 10;
+                }
+                program.push(instruction);
+                for (_, value) in &operands {
+                    program.push(resolve(value)?);
+                }
+            }
+        }
+    }
+    Ok(program)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssembleError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for AssembleError {}
+
+enum Expr {
+    Number(Word),
+    Label(String, Word),
+}
+
+impl Expr {
+    fn parse(text: &str) -> Result<Expr, String> {
+        let text = text.trim();
+        if let Ok(value) = text.parse() {
+            return Ok(Expr::Number(value));
+        }
+        let (name, offset) = match text.rfind(['+', '-']) {
+            Some(split) if split > 0 => {
+                let offset = text[split..].parse().map_err(|_| format!("Invalid offset in '{}'", text))?;
+                (text[..split].trim(), offset)
+            }
+            _ => (text, 0),
+        };
+        if !is_identifier(name) {
+            return Err(format!("Invalid value '{}'", text));
+        }
+        Ok(Expr::Label(name.to_string(), offset))
+    }
+
+    fn resolve(&self, labels: &HashMap<String, Word>) -> Result<Word, String> {
+        match self {
+            Expr::Number(value) => Ok(*value),
+            Expr::Label(name, offset) => {
+                let address = labels.get(name).ok_or_else(|| format!("Unknown label '{}'", name))?;
+                address
+                    .checked_add(*offset)
+                    .ok_or_else(|| format!("'{}{:+}' does not fit in a word", name, offset))
+            }
+        }
+    }
+}
+
+enum Statement {
+    Instruction(&'static OpcodeInfo, Vec<(ParameterMode, Expr)>),
+    Data(Vec<Expr>),
+}
+
+impl Statement {
+    fn width(&self) -> usize {
+        match self {
+            Statement::Instruction(_, operands) => operands.len() + 1,
+            Statement::Data(values) => values.len(),
+        }
+    }
+}
+
+fn parse_statement(text: &str) -> Result<Statement, String> {
+    let (mnemonic, rest) = match text.find(char::is_whitespace) {
+        Some(split) => (&text[..split], text[split..].trim()),
+        None => (text, ""),
+    };
+    // `->` separates the written operand just like a comma does.
+    let mut operands = Vec::new();
+    for part in rest.split("->") {
+        let part = part.trim();
+        if !part.is_empty() {
+            operands.extend(part.split(',').map(|operand| operand.trim()));
+        }
+    }
+
+    if mnemonic.eq_ignore_ascii_case("data") {
+        let values = operands.iter().map(|value| Expr::parse(value)).collect::<Result<Vec<_>, _>>()?;
+        if values.is_empty() {
+            return Err("DATA needs at least one value".to_string());
+        }
+        return Ok(Statement::Data(values));
+    }
+
+    let info = OPCODES
+        .iter()
+        .find(|info| info.mnemonic.eq_ignore_ascii_case(mnemonic))
+        .ok_or_else(|| format!("Unknown mnemonic '{}'", mnemonic))?;
+    if operands.len() != info.params.len() {
+        return Err(format!(
+            "{} takes {} operands, found {}",
+            info.mnemonic,
+            info.params.len(),
+            operands.len()
+        ));
+    }
+    let mut parsed = Vec::new();
+    for (operand, role) in operands.iter().zip(info.params) {
+        let (mode, value) = parse_operand(operand)?;
+        if *role == Role::Write && mode == ParameterMode::Immediate {
+            return Err(format!("Write operand '{}' of {} cannot be immediate", operand, info.mnemonic));
+        }
+        parsed.push((mode, value));
+    }
+    Ok(Statement::Instruction(info, parsed))
+}
+
+fn parse_operand(text: &str) -> Result<(ParameterMode, Expr), String> {
+    if let Some(value) = text.strip_prefix('#') {
+        return Ok((ParameterMode::Immediate, Expr::parse(value)?));
+    }
+    let inner = text
+        .strip_prefix('[')
+        .and_then(|inner| inner.strip_suffix(']'))
+        .ok_or_else(|| format!("Invalid operand '{}', expected #n, [n] or [rb+n]", text))?
+        .trim();
+    let lower = inner.to_ascii_lowercase();
+    if lower == "rb" {
+        return Ok((ParameterMode::Relative, Expr::Number(0)));
+    }
+    if lower.starts_with("rb+") || lower.starts_with("rb-") {
+        let offset = inner[2..].trim_start_matches('+');
+        return Ok((ParameterMode::Relative, Expr::parse(offset)?));
+    }
+    Ok((ParameterMode::Position, Expr::parse(inner)?))
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) if first.is_ascii_alphabetic() || first == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::disassembler::listing;
+
+    #[test]
+    fn listing_round_trips() {
+        let source = "
+            start:  in -> [x]
+                    arb #10
+                    mul [x], #2 -> [rb-3]
+                    add [rb-3], [rb+1] -> [x+1]
+                    out [x+1]
+                    jt #1, #start
+            x:      data 0, 0, -7
+        ";
+        let program = assemble(source).unwrap();
+        assert_eq!(program[..3], [3, 17, 109]);
+        assert_eq!(program[4..8], [21002, 17, 2, -3]);
+        assert_eq!(program[17..], [0, 0, -7]);
+        assert_eq!(assemble(&listing(&program)).unwrap(), program);
+    }
+
+    #[test]
+    fn label_offset_overflow_is_an_error() {
+        let error = assemble(&format!("data x+{}\nx: data 0", Word::MAX)).unwrap_err();
+        assert_eq!(error.line, 1);
+    }
+}
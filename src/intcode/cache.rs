@@ -0,0 +1,80 @@
+use super::{Instruction, PC};
+
+/// Instructions decoded from the loaded program, by pc.
+///
+/// Entries are never thrown away. Instead every write marks its word as
+/// modified, and an entry is only used while none of the words it was decoded
+/// from are modified. `reset` restores the program, so it only has to clear
+/// the marks, and a program that patches its own operands on every run
+/// still gets cache hits on the next run.
+#[derive(Debug, Clone)]
+pub(super) struct DecodeCache {
+    entries: Vec<Option<Instruction>>,
+    modified: Vec<bool>,
+    dirty: Vec<usize>,
+}
+
+impl DecodeCache {
+    pub(super) fn new(program_len: usize) -> Self {
+        DecodeCache {
+            entries: vec![None; program_len],
+            modified: vec![false; program_len],
+            dirty: Vec::new(),
+        }
+    }
+
+    pub(super) fn get(&self, pc: PC) -> Option<Instruction> {
+        let instruction = self.entries.get(pc).copied().flatten()?;
+        if self.is_modified(pc, instruction.params) {
+            return None;
+        }
+        Some(instruction)
+    }
+
+    /// Caches an instruction decoded from memory, if that memory still holds
+    /// the loaded program.
+    pub(super) fn insert(&mut self, pc: PC, instruction: Instruction) {
+        if pc + instruction.params >= self.entries.len() || self.is_modified(pc, instruction.params) {
+            return;
+        }
+        self.entries[pc] = Some(instruction);
+    }
+
+    /// Called for every write to `address`.
+    pub(super) fn invalidate(&mut self, address: usize) {
+        if let Some(modified) = self.modified.get_mut(address) {
+            if !*modified {
+                *modified = true;
+                self.dirty.push(address);
+            }
+        }
+    }
+
+    /// Memory holds the loaded program again.
+    pub(super) fn reset(&mut self) {
+        for address in self.dirty.drain(..) {
+            self.modified[address] = false;
+        }
+    }
+
+    /// Memory was replaced wholesale, `differs` tells which words are not
+    /// the loaded program.
+    pub(super) fn resync<F: Fn(usize) -> bool>(&mut self, differs: F) {
+        self.reset();
+        for address in 0..self.modified.len() {
+            if differs(address) {
+                self.invalidate(address);
+            }
+        }
+    }
+
+    /// Drops every entry, keeping the modified marks. Needed when the
+    /// parameter count of an opcode changes.
+    pub(super) fn clear(&mut self) {
+        self.entries.iter_mut().for_each(|entry| *entry = None);
+    }
+
+    fn is_modified(&self, pc: PC, params: usize) -> bool {
+        self.modified[pc..=pc + params].iter().any(|modified| *modified)
+    }
+}
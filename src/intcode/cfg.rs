@@ -0,0 +1,228 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use super::disassembler::{decode_at, Line, Operand};
+use super::{ParameterMode, Role, Word};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EdgeKind {
+    Jump,
+    Fallthrough,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Edge {
+    pub target: usize,
+    pub kind: EdgeKind,
+}
+
+/// Something the static analysis cannot see through.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flag {
+    /// A jump whose target is read from memory.
+    IndirectJump { pc: usize },
+    /// A write into the words of a reachable instruction.
+    SelfModifying { pc: usize, address: usize },
+    /// A relative mode write, which may hit code.
+    IndirectWrite { pc: usize },
+    /// Control reaches a word that does not decode as an instruction.
+    InvalidInstruction { address: usize },
+    /// A jump or fallthrough leaves the program.
+    OutsideProgram { pc: usize, target: Word },
+}
+
+impl fmt::Display for Flag {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Flag::IndirectJump { pc } => write!(f, "{:04}: indirect jump", pc),
+            Flag::SelfModifying { pc, address } => write!(f, "{:04}: writes code at {}", pc, address),
+            Flag::IndirectWrite { pc } => write!(f, "{:04}: relative write, may modify code", pc),
+            Flag::InvalidInstruction { address } => write!(f, "{:04}: invalid instruction", address),
+            Flag::OutsideProgram { pc, target } => write!(f, "{:04}: leaves the program to {}", pc, target),
+        }
+    }
+}
+
+impl Flag {
+    /// The address the flag is reported at.
+    pub fn address(&self) -> usize {
+        match *self {
+            Flag::IndirectJump { pc }
+            | Flag::SelfModifying { pc, .. }
+            | Flag::IndirectWrite { pc }
+            | Flag::OutsideProgram { pc, .. } => pc,
+            Flag::InvalidInstruction { address } => address,
+        }
+    }
+}
+
+/// Straight-line code: only the first line is jumped to and only the last one
+/// jumps.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Block {
+    pub start: usize,
+    pub lines: Vec<Line>,
+    pub successors: Vec<Edge>,
+}
+
+impl Block {
+    /// The address just past the block.
+    pub fn end(&self) -> usize {
+        self.lines.last().map_or(self.start, |line| line.address() + line.width())
+    }
+}
+
+/// The basic blocks reachable from address 0, following immediate jump
+/// targets and fallthroughs, keyed by start address.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ControlFlowGraph {
+    pub blocks: BTreeMap<usize, Block>,
+    pub flags: Vec<Flag>,
+}
+
+impl ControlFlowGraph {
+    pub fn build(program: &[Word]) -> Self {
+        let mut flags = Vec::new();
+        let mut lines = BTreeMap::new();
+        let mut successors = BTreeMap::new();
+        let mut work = vec![0];
+        while let Some(address) = work.pop() {
+            if lines.contains_key(&address) || address >= program.len() {
+                continue;
+            }
+            let line = decode_at(program, address);
+            let edges = edges(&line, program.len(), &mut flags);
+            work.extend(edges.iter().map(|edge| edge.target));
+            successors.insert(address, edges);
+            lines.insert(address, line);
+        }
+
+        let code: BTreeSet<usize> = lines
+            .values()
+            .flat_map(|line| line.address()..line.address() + line.width())
+            .collect();
+        for line in lines.values() {
+            if let Line::Instruction { address, operands, .. } = line {
+                for operand in operands.iter().filter(|operand| operand.role == Role::Write) {
+                    match operand.mode {
+                        ParameterMode::Position if operand.value >= 0 && code.contains(&(operand.value as usize)) => {
+                            flags.push(Flag::SelfModifying { pc: *address, address: operand.value as usize })
+                        }
+                        ParameterMode::Relative => flags.push(Flag::IndirectWrite { pc: *address }),
+                        _ => {}
+                    }
+                }
+            }
+        }
+        flags.sort_by_key(|flag| flag.address());
+
+        let mut leaders: BTreeSet<usize> = BTreeSet::new();
+        leaders.insert(0);
+        for (address, edges) in &successors {
+            if line_is_terminator(&lines[address]) {
+                leaders.extend(edges.iter().map(|edge| edge.target));
+            }
+        }
+
+        let mut blocks = BTreeMap::new();
+        for &start in leaders.iter().filter(|start| lines.contains_key(start)) {
+            let mut block = Block { start, lines: Vec::new(), successors: Vec::new() };
+            let mut address = start;
+            loop {
+                let line = lines[&address].clone();
+                let edges = &successors[&address];
+                let next = address + line.width();
+                block.lines.push(line);
+                let ends = line_is_terminator(&block.lines[block.lines.len() - 1])
+                    || leaders.contains(&next)
+                    || !lines.contains_key(&next);
+                if ends {
+                    block.successors = edges.clone();
+                    break;
+                }
+                address = next;
+            }
+            blocks.insert(start, block);
+        }
+        ControlFlowGraph { blocks, flags }
+    }
+
+    /// Graphviz DOT source. Flagged blocks are drawn red with their flags
+    /// listed, fallthrough edges are dashed.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph intcode {\n    node [shape=box, fontname=\"monospace\"];\n");
+        for block in self.blocks.values() {
+            let flags: Vec<&Flag> = self
+                .flags
+                .iter()
+                .filter(|flag| (block.start..block.end()).contains(&flag.address()))
+                .collect();
+            let mut label: String = block.lines.iter().map(|line| format!("{}\\l", line)).collect();
+            for flag in &flags {
+                label.push_str(&format!("! {}\\l", flag));
+            }
+            let color = if flags.is_empty() { "" } else { ", color=red" };
+            dot.push_str(&format!("    b{} [label=\"{}\"{}];\n", block.start, label, color));
+            for edge in &block.successors {
+                let style = match edge.kind {
+                    EdgeKind::Jump => "",
+                    EdgeKind::Fallthrough => " [style=dashed]",
+                };
+                dot.push_str(&format!("    b{} -> b{}{};\n", block.start, edge.target, style));
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+fn line_is_terminator(line: &Line) -> bool {
+    match line {
+        Line::Instruction { info, .. } => matches!(info.opcode, 5 | 6 | 99),
+        Line::Data { .. } => true,
+    }
+}
+
+/// Where control can go after `line`, flagging what cannot be followed.
+fn edges(line: &Line, len: usize, flags: &mut Vec<Flag>) -> Vec<Edge> {
+    let (address, info, operands) = match line {
+        Line::Instruction { address, info, operands } => (*address, info, operands),
+        Line::Data { address, .. } => {
+            flags.push(Flag::InvalidInstruction { address: *address });
+            return Vec::new();
+        }
+    };
+    let mut edges = Vec::new();
+    let mut add = |target: Word, kind: EdgeKind, flags: &mut Vec<Flag>| {
+        if target >= 0 && (target as usize) < len {
+            edges.push(Edge { target: target as usize, kind });
+        } else {
+            flags.push(Flag::OutsideProgram { pc: address, target });
+        }
+    };
+    let next = (address + line.width()) as Word;
+    match info.opcode {
+        99 => {}
+        5 | 6 => {
+            let jumps_if_nonzero = info.opcode == 5;
+            let (jumps, falls_through) = match operands[0] {
+                Operand { mode: ParameterMode::Immediate, value, .. } => {
+                    let jumps = (value != 0) == jumps_if_nonzero;
+                    (jumps, !jumps)
+                }
+                _ => (true, true),
+            };
+            if jumps {
+                match operands[1] {
+                    Operand { mode: ParameterMode::Immediate, value, .. } => add(value, EdgeKind::Jump, flags),
+                    _ => flags.push(Flag::IndirectJump { pc: address }),
+                }
+            }
+            if falls_through {
+                add(next, EdgeKind::Fallthrough, flags);
+            }
+        }
+        _ => add(next, EdgeKind::Fallthrough, flags),
+    }
+    edges
+}
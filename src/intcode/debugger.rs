@@ -0,0 +1,403 @@
+use std::collections::BTreeSet;
+use std::io::{stdin, stdout, BufRead, Write};
+
+use super::disassembler::{decode_memory, Line};
+use super::dump;
+use super::journal::JournalEntry;
+use super::{decode, InputSource, Intcode, OpcodeInfo, OutputSink, Status, Word, ADDRESS_LIMIT, PC, OPCODES};
+
+/// Instructions kept in the undo journal for reverse stepping.
+const JOURNAL_CAPACITY: usize = 1_000_000;
+
+/// Most words or lines a single memory or listing command shows.
+const MAX_COUNT: usize = 4096;
+
+const HELP: &str = "\
+Commands:
+  s, step [n]           execute n instructions (default 1)
+  c, continue           run until a breakpoint, watchpoint, input request or halt
+  rs, rstep [n]         undo n instructions (default 1)
+  rc, rcontinue         run backwards until a breakpoint or watchpoint
+  lw, lastwrite <addr>  run backwards to just before addr was last written
+  b, break <addr>       break when pc reaches addr
+  bo, breakop <op>      break before executing opcode (number or mnemonic)
+  w, watch <addr>       stop after addr is written
+  d, delete <addr>      remove breakpoint and watchpoint at addr
+  do, deleteop <op>     remove opcode breakpoint
+  info                  list breakpoints and watchpoints
+  r, regs               show registers
+  x, mem <addr> [n]     show n words of memory (default 8)
+  l, list [addr] [n]    disassemble n instructions (default 8) from addr or pc
+  dump [addr] [n]       show n words (default 64) from addr (default 0) with disassembly
+  diff                  show words that differ from the loaded program
+  set <addr> <value>    write value to memory
+  pc <addr>             set pc
+  rb <value>            set relative base
+  i, input <value>...   queue input values
+  q, quit               leave the debugger
+An empty line repeats the previous command.";
+
+/// Why a `continue` or `step` stopped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Stop {
+    Step,
+    Breakpoint(PC),
+    Opcode(PC),
+    Watchpoint { address: usize, old: Word, new: Word },
+    WaitingForInput,
+    Halted,
+    Fault(String),
+    JournalStart,
+}
+
+pub struct Debugger<I, O> {
+    machine: Intcode<I, O>,
+    breakpoints: BTreeSet<PC>,
+    opcode_breaks: BTreeSet<PC>,
+    watches: BTreeSet<usize>,
+}
+
+impl<I: InputSource, O: OutputSink> Debugger<I, O> {
+    pub fn new(mut machine: Intcode<I, O>) -> Self {
+        machine.enable_journal(JOURNAL_CAPACITY);
+        Debugger {
+            machine,
+            breakpoints: BTreeSet::new(),
+            opcode_breaks: BTreeSet::new(),
+            watches: BTreeSet::new(),
+        }
+    }
+
+    pub fn machine(&self) -> &Intcode<I, O> {
+        &self.machine
+    }
+
+    pub fn machine_mut(&mut self) -> &mut Intcode<I, O> {
+        &mut self.machine
+    }
+
+    pub fn into_machine(self) -> Intcode<I, O> {
+        self.machine
+    }
+
+    pub fn add_breakpoint(&mut self, pc: PC) {
+        self.breakpoints.insert(pc);
+    }
+
+    pub fn add_opcode_break(&mut self, opcode: PC) {
+        self.opcode_breaks.insert(opcode);
+    }
+
+    pub fn add_watch(&mut self, address: usize) {
+        self.watches.insert(address);
+    }
+
+    /// Executes one instruction, passing any output to the machine's sink.
+    pub fn step(&mut self) -> Stop {
+        match self.machine.step() {
+            Ok(Status::Running) => {}
+            Ok(Status::Output(value)) => self.machine.sink_mut().write(value),
+            Ok(Status::WaitingForInput) => return Stop::WaitingForInput,
+            Ok(Status::Halted) => return Stop::Halted,
+            Ok(Status::LimitReached { limit, executed, .. }) => {
+                return Stop::Fault(self.machine.limit_error(limit, executed).to_string())
+            }
+            Err(error) => return Stop::Fault(error.to_string()),
+        }
+        match self.machine.last_write() {
+            Some(write) if self.watches.contains(&write.address) => Stop::Watchpoint {
+                address: write.address,
+                old: write.old,
+                new: write.new,
+            },
+            _ => Stop::Step,
+        }
+    }
+
+    /// Runs until something worth stopping for happens. The instruction at the
+    /// current pc is always executed, so continuing from a breakpoint works.
+    pub fn cont(&mut self) -> Stop {
+        loop {
+            let stop = self.step();
+            if stop != Stop::Step {
+                return stop;
+            }
+            let pc = self.machine.pc();
+            if self.breakpoints.contains(&pc) {
+                return Stop::Breakpoint(pc);
+            }
+            let (opcode, _) = decode(self.machine[pc]);
+            if self.opcode_breaks.contains(&opcode) {
+                return Stop::Opcode(opcode);
+            }
+        }
+    }
+
+    /// Undoes one instruction.
+    pub fn step_back(&mut self) -> Stop {
+        match self.machine.step_back() {
+            Some(_) => Stop::Step,
+            None => Stop::JournalStart,
+        }
+    }
+
+    /// Runs backwards until the pc reaches a breakpoint or a write to a
+    /// watched address has been undone.
+    pub fn reverse_cont(&mut self) -> Stop {
+        loop {
+            let entry = match self.machine.step_back() {
+                Some(entry) => entry,
+                None => return Stop::JournalStart,
+            };
+            if let Some(write) = entry.write {
+                if self.watches.contains(&write.address) {
+                    return Stop::Watchpoint {
+                        address: write.address,
+                        old: write.old,
+                        new: write.new,
+                    };
+                }
+            }
+            if self.breakpoints.contains(&entry.pc) {
+                return Stop::Breakpoint(entry.pc);
+            }
+        }
+    }
+
+    /// Runs backwards to the state just before `address` was last written.
+    pub fn back_to_write(&mut self, address: usize) -> Stop {
+        loop {
+            match self.machine.step_back() {
+                Some(JournalEntry { write: Some(write), .. }) if write.address == address => {
+                    return Stop::Watchpoint {
+                        address,
+                        old: write.old,
+                        new: write.new,
+                    }
+                }
+                Some(_) => {}
+                None => return Stop::JournalStart,
+            }
+        }
+    }
+
+    /// Reads commands from stdin until `quit` or end of input.
+    pub fn repl(&mut self) {
+        println!("Intcode debugger, type 'help' for commands.");
+        self.print_current();
+        let mut previous = String::new();
+        let stdin = stdin();
+        let mut lines = stdin.lock().lines();
+        loop {
+            print!("(icdb) ");
+            stdout().flush().expect("Failed to flush stdout");
+            let line = match lines.next() {
+                Some(Ok(line)) => line,
+                _ => break,
+            };
+            let line = if line.trim().is_empty() { previous.clone() } else { line };
+            if !self.command(&line) {
+                break;
+            }
+            previous = line;
+        }
+    }
+
+    /// Executes one debugger command. Returns false when the user asked to quit.
+    pub fn command(&mut self, line: &str) -> bool {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let (command, args) = match words.split_first() {
+            Some((command, args)) => (*command, args),
+            None => return true,
+        };
+        let result = match command {
+            "s" | "step" => parse_arg(args.first(), 1).map(|count: usize| {
+                for _ in 0..count {
+                    let stop = self.step();
+                    if stop != Stop::Step {
+                        self.report(&stop);
+                        break;
+                    }
+                }
+                self.print_current();
+            }),
+            "c" | "continue" => {
+                let stop = self.cont();
+                self.report(&stop);
+                self.print_current();
+                Ok(())
+            }
+            "rs" | "rstep" => parse_arg(args.first(), 1).map(|count: usize| {
+                for _ in 0..count {
+                    let stop = self.step_back();
+                    if stop != Stop::Step {
+                        self.report(&stop);
+                        break;
+                    }
+                }
+                self.print_current();
+            }),
+            "rc" | "rcontinue" => {
+                let stop = self.reverse_cont();
+                self.report(&stop);
+                self.print_current();
+                Ok(())
+            }
+            "lw" | "lastwrite" => parse_required(args.first()).map(|address| {
+                let stop = self.back_to_write(address);
+                self.report(&stop);
+                self.print_current();
+            }),
+            "b" | "break" => parse_required(args.first()).map(|pc| self.add_breakpoint(pc)),
+            "bo" | "breakop" => parse_opcode(args.first(), &self.extensions()).map(|opcode| self.add_opcode_break(opcode)),
+            "w" | "watch" => parse_required(args.first()).map(|address| self.add_watch(address)),
+            "d" | "delete" => parse_required(args.first()).map(|address: usize| {
+                self.breakpoints.remove(&address);
+                self.watches.remove(&address);
+            }),
+            "do" | "deleteop" => parse_opcode(args.first(), &self.extensions()).map(|opcode| {
+                self.opcode_breaks.remove(&opcode);
+            }),
+            "info" => {
+                println!("Breakpoints: {:?}", self.breakpoints);
+                println!("Opcode breaks: {:?}", self.opcode_breaks);
+                println!("Watchpoints: {:?}", self.watches);
+                Ok(())
+            }
+            "r" | "regs" => {
+                println!("pc: {} rb: {} pending input: {:?}", self.machine.pc(), self.machine.relative_base(), self.machine.pending_input);
+                self.print_current();
+                Ok(())
+            }
+            "x" | "mem" => parse_required(args.first()).and_then(|start: usize| {
+                let end = parse_end(start, args.get(1), 8)?;
+                for row in (start..end).step_by(8) {
+                    let words: Vec<String> = (row..row.saturating_add(8).min(end))
+                        .map(|address| self.machine[address].to_string())
+                        .collect();
+                    println!("{:04}: {}", row, words.join(" "));
+                }
+                Ok(())
+            }),
+            "l" | "list" => parse_arg(args.first(), self.machine.pc()).and_then(|start: usize| {
+                let count = parse_count(args.get(1), 8)?;
+                let mut address = Some(start);
+                for _ in 0..count {
+                    let line = match address {
+                        Some(address) => self.line_at(address),
+                        None => break,
+                    };
+                    println!("{}", line);
+                    address = line.address().checked_add(line.width());
+                }
+                Ok(())
+            }),
+            "dump" => parse_arg(args.first(), 0).and_then(|start: usize| {
+                let end = parse_end(start, args.get(1), 64)?;
+                print!("{}", self.machine.dump(start..end, 8));
+                Ok(())
+            }),
+            "diff" => {
+                let changes = self.machine.memory_diff();
+                print!("{}", dump::format_diff(self.machine.original_memory(), &changes));
+                Ok(())
+            }
+            "set" => parse_required(args.first()).and_then(|address: usize| {
+                if address >= ADDRESS_LIMIT {
+                    return Err(format!("Address {} out of range", address));
+                }
+                let value = parse_required(args.get(1))?;
+                self.machine[address] = value;
+                Ok(())
+            }),
+            "pc" => parse_required(args.first()).map(|pc| {
+                self.machine.set_pc(pc);
+                self.print_current();
+            }),
+            "rb" => parse_required(args.first()).map(|value| self.machine.set_relative_base(value)),
+            "i" | "input" => args
+                .iter()
+                .map(|arg| parse_required(Some(arg)))
+                .collect::<Result<Vec<Word>, String>>()
+                .map(|values| values.into_iter().for_each(|value| self.machine.feed(value))),
+            "h" | "help" => {
+                println!("{}", HELP);
+                Ok(())
+            }
+            "q" | "quit" => return false,
+            _ => Err(format!("Unknown command '{}', type 'help' for commands", command)),
+        };
+        if let Err(message) = result {
+            println!("{}", message);
+        }
+        true
+    }
+
+    /// The machine's custom opcodes, so they disassemble and can be broken on.
+    fn extensions(&self) -> Vec<OpcodeInfo> {
+        self.machine.extensions().copied().collect()
+    }
+
+    fn line_at(&self, address: usize) -> Line {
+        decode_memory(self.machine.memory(), address, &self.extensions())
+    }
+
+    fn print_current(&self) {
+        println!("=> {}", self.line_at(self.machine.pc()));
+    }
+
+    fn report(&self, stop: &Stop) {
+        match stop {
+            Stop::Step => {}
+            Stop::Breakpoint(pc) => println!("Breakpoint at {}", pc),
+            Stop::Opcode(opcode) => println!("Break on opcode {}", opcode),
+            Stop::Watchpoint { address, old, new } => {
+                println!("Watchpoint: [{}] written, {} -> {}", address, old, new)
+            }
+            Stop::WaitingForInput => println!("Waiting for input, queue values with 'input <value>'"),
+            Stop::Halted => println!("Program halted"),
+            Stop::Fault(message) => println!("Fault: {}", message),
+            Stop::JournalStart => println!("Reached the start of the journal"),
+        }
+    }
+}
+
+fn parse_required<T: std::str::FromStr>(arg: Option<&&str>) -> Result<T, String> {
+    let arg = arg.ok_or_else(|| "Missing argument".to_string())?;
+    arg.parse().map_err(|_| format!("Invalid argument '{}'", arg))
+}
+
+fn parse_arg<T: std::str::FromStr>(arg: Option<&&str>, default: T) -> Result<T, String> {
+    match arg {
+        Some(_) => parse_required(arg),
+        None => Ok(default),
+    }
+}
+
+/// A count argument of at most `MAX_COUNT`.
+fn parse_count(arg: Option<&&str>, default: usize) -> Result<usize, String> {
+    let count = parse_arg(arg, default)?;
+    if count > MAX_COUNT {
+        return Err(format!("Count {} is larger than {}", count, MAX_COUNT));
+    }
+    Ok(count)
+}
+
+/// The end of a range of `count` words starting at `start`.
+fn parse_end(start: usize, arg: Option<&&str>, default: usize) -> Result<usize, String> {
+    let count = parse_count(arg, default)?;
+    start.checked_add(count).ok_or_else(|| "Invalid range".to_string())
+}
+
+fn parse_opcode(arg: Option<&&str>, extensions: &[OpcodeInfo]) -> Result<PC, String> {
+    let arg = arg.ok_or_else(|| "Missing argument".to_string())?;
+    if let Ok(opcode) = arg.parse() {
+        return Ok(opcode);
+    }
+    OPCODES
+        .iter()
+        .chain(extensions)
+        .find(|info| info.mnemonic.eq_ignore_ascii_case(arg))
+        .map(|info| info.opcode)
+        .ok_or_else(|| format!("Unknown opcode '{}'", arg))
+}
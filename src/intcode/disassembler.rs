@@ -0,0 +1,138 @@
+use std::fmt;
+
+use super::{decode, opcode_info, Memory, OpcodeInfo, ParameterMode, Role, Word};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Operand {
+    pub mode: ParameterMode,
+    pub role: Role,
+    pub value: Word,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Line {
+    Instruction {
+        address: usize,
+        info: OpcodeInfo,
+        operands: Vec<Operand>,
+    },
+    Data {
+        address: usize,
+        value: Word,
+    },
+}
+
+impl Line {
+    pub fn address(&self) -> usize {
+        match self {
+            Line::Instruction { address, .. } | Line::Data { address, .. } => *address,
+        }
+    }
+
+    /// Number of words this line covers.
+    pub fn width(&self) -> usize {
+        match self {
+            Line::Instruction { operands, .. } => operands.len() + 1,
+            Line::Data { .. } => 1,
+        }
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.mode {
+            ParameterMode::Position => write!(f, "[{}]", self.value),
+            ParameterMode::Immediate => write!(f, "#{}", self.value),
+            ParameterMode::Relative if self.value < 0 => write!(f, "[rb{}]", self.value),
+            ParameterMode::Relative => write!(f, "[rb+{}]", self.value),
+        }
+    }
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04}: ", self.address())?;
+        match self {
+            Line::Instruction { info, operands, .. } => {
+                write!(f, "{}", info.mnemonic)?;
+                let reads: Vec<String> = operands
+                    .iter()
+                    .filter(|operand| operand.role == Role::Read)
+                    .map(|operand| operand.to_string())
+                    .collect();
+                if !reads.is_empty() {
+                    write!(f, " {}", reads.join(", "))?;
+                }
+                for operand in operands.iter().filter(|operand| operand.role == Role::Write) {
+                    write!(f, " -> {}", operand)?;
+                }
+                Ok(())
+            }
+            Line::Data { value, .. } => write!(f, "DATA {}", value),
+        }
+    }
+}
+
+/// Decodes the word at `address`. Words that are not a valid instruction, or
+/// whose operands run past the end of the program, become `DATA`.
+pub fn decode_at(program: &[Word], address: usize) -> Line {
+    decode_with(|address| program[address], program.len(), address, &[])
+}
+
+/// Decodes the word at `address` of a running machine's memory, which has no
+/// end. `extensions` are the custom opcodes registered on the machine.
+pub fn decode_memory(memory: &Memory, address: usize, extensions: &[OpcodeInfo]) -> Line {
+    decode_with(|address| memory[address], usize::MAX, address, extensions)
+}
+
+fn decode_with<F: Fn(usize) -> Word>(fetch: F, end: usize, address: usize, extensions: &[OpcodeInfo]) -> Line {
+    let value = fetch(address);
+    let data = Line::Data { address, value };
+    let (opcode, mut mode_iter) = decode(value);
+    let info = match opcode_info(opcode).or_else(|| extensions.iter().find(|info| info.opcode == opcode)) {
+        Some(info) => *info,
+        None => return data,
+    };
+    match address.checked_add(info.params.len()) {
+        Some(last) if last < end => {}
+        _ => return data,
+    }
+    let mut operands = Vec::new();
+    for (i, role) in info.params.iter().enumerate() {
+        let mode = match mode_iter.next() {
+            Ok(mode) => mode,
+            Err(_) => return data,
+        };
+        if *role == Role::Write && mode == ParameterMode::Immediate {
+            return data;
+        }
+        operands.push(Operand {
+            mode,
+            role: *role,
+            value: fetch(address + i + 1),
+        });
+    }
+    // Leftover mode digits would not survive a round trip.
+    if !mode_iter.is_exhausted() {
+        return data;
+    }
+    Line::Instruction { address, info, operands }
+}
+
+pub fn disassemble(program: &[Word]) -> Vec<Line> {
+    let mut lines = Vec::new();
+    let mut address = 0;
+    while address < program.len() {
+        let line = decode_at(program, address);
+        address += line.width();
+        lines.push(line);
+    }
+    lines
+}
+
+pub fn listing(program: &[Word]) -> String {
+    disassemble(program)
+        .iter()
+        .map(|line| format!("{}\n", line))
+        .collect()
+}
@@ -0,0 +1,109 @@
+use std::ops::Range;
+
+use super::disassembler::{decode_at, decode_memory, Line};
+use super::{Memory, MemoryWrite, OpcodeInfo, Word};
+
+/// Formats `range` of memory as rows of `width` words, each row followed by
+/// the disassembly of the instructions starting in it:
+///
+/// ```text
+/// 0000:  1  0  0  3  1  1  2  3 | 0000: ADD [0], [0] -> [3]  0004: ADD [1], [2] -> [3]
+/// ```
+///
+/// Decoding starts at `range.start` and follows instruction widths.
+/// `extensions` are the custom opcodes registered on the machine.
+pub fn dump(memory: &Memory, range: Range<usize>, width: usize, extensions: &[OpcodeInfo]) -> String {
+    let width = width.max(1);
+    let column = range.clone().map(|address| memory[address].to_string().len()).max().unwrap_or(1);
+    let mut lines = Vec::new();
+    let mut address = range.start;
+    while address < range.end {
+        let line = decode_memory(memory, address, extensions);
+        address = address.saturating_add(line.width());
+        lines.push(line);
+    }
+
+    let mut text = String::new();
+    let mut lines = lines.into_iter().peekable();
+    let mut row = range.start;
+    while row < range.end {
+        let end = row.saturating_add(width).min(range.end);
+        text.push_str(&format!("{:04}:", row));
+        for address in row..end {
+            text.push_str(&format!(" {:>1$}", memory[address], column));
+        }
+        let mut annotations = Vec::new();
+        while let Some(line) = lines.next_if(|line| line.address() < end) {
+            if let Line::Instruction { .. } = line {
+                annotations.push(line.to_string());
+            }
+        }
+        if !annotations.is_empty() {
+            let padding = (width - (end - row)) * (column + 1);
+            text.push_str(&format!("{:1$} | {2}", "", padding, annotations.join("  ")));
+        }
+        text.push('\n');
+        row = end;
+    }
+    text
+}
+
+/// Every address whose word differs from `original`, in address order.
+/// Words past the end of `original` count as changed when they are not zero.
+pub fn diff(original: &[Word], memory: &Memory) -> Vec<MemoryWrite> {
+    let mut changes: Vec<MemoryWrite> = original
+        .iter()
+        .enumerate()
+        .filter(|(address, old)| memory[*address] != **old)
+        .map(|(address, old)| MemoryWrite { address, old: *old, new: memory[address] })
+        .collect();
+    changes.extend(
+        memory
+            .iter()
+            .filter(|(address, new)| *address >= original.len() && *new != 0)
+            .map(|(address, new)| MemoryWrite { address, old: 0, new }),
+    );
+    changes
+}
+
+/// One line per change, with the original instruction the word belongs to:
+///
+/// ```text
+/// 0001: 0 -> 12  in 0000: ADD [0], [0] -> [3]
+/// ```
+pub fn format_diff(original: &[Word], changes: &[MemoryWrite]) -> String {
+    let mut owners = vec![None; original.len()];
+    let mut address = 0;
+    while address < original.len() {
+        let line = decode_at(original, address);
+        if let Line::Instruction { .. } = line {
+            for owner in &mut owners[address..address + line.width()] {
+                *owner = Some(line.clone());
+            }
+        }
+        address += line.width();
+    }
+
+    let mut text = String::new();
+    for change in changes {
+        text.push_str(&format!("{:04}: {} -> {}", change.address, change.old, change.new));
+        if let Some(Some(line)) = owners.get(change.address) {
+            text.push_str(&format!("  in {}", line));
+        }
+        text.push('\n');
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dump_near_the_top_of_memory() {
+        let memory = Memory::from(vec![1, 0, 0, 3, 99]);
+        assert_eq!(dump(&memory, 0..5, 8, &[]), "0000:  1  0  0  3 99          | 0000: ADD [0], [0] -> [3]  0004: HLT\n");
+        let start = usize::MAX - 5;
+        assert_eq!(dump(&memory, start..usize::MAX, 8, &[]).lines().count(), 1);
+    }
+}
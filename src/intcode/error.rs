@@ -0,0 +1,77 @@
+use std::error::Error;
+use std::fmt;
+
+use super::{Limit, Word};
+
+/// A fault raised by the VM. Every variant records the `pc` of the faulting
+/// instruction and its raw instruction word.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IntcodeError {
+    UnknownOpcode { pc: usize, instruction: Word },
+    InvalidParameterMode { pc: usize, instruction: Word, mode: Word },
+    NegativeAddress { pc: usize, instruction: Word, address: Word },
+    AddressOutOfRange { pc: usize, instruction: Word, address: Word },
+    ImmediateWrite { pc: usize, instruction: Word },
+    InputExhausted { pc: usize, instruction: Word },
+    LimitReached { pc: usize, instruction: Word, limit: Limit, executed: u64 },
+    Overflow { pc: usize, instruction: Word },
+    /// A custom opcode stored to memory more than once.
+    SecondWrite { pc: usize, instruction: Word },
+}
+
+impl IntcodeError {
+    pub fn pc(&self) -> usize {
+        match *self {
+            IntcodeError::UnknownOpcode { pc, .. }
+            | IntcodeError::InvalidParameterMode { pc, .. }
+            | IntcodeError::NegativeAddress { pc, .. }
+            | IntcodeError::AddressOutOfRange { pc, .. }
+            | IntcodeError::ImmediateWrite { pc, .. }
+            | IntcodeError::InputExhausted { pc, .. }
+            | IntcodeError::LimitReached { pc, .. }
+            | IntcodeError::Overflow { pc, .. }
+            | IntcodeError::SecondWrite { pc, .. } => pc,
+        }
+    }
+
+    pub fn instruction(&self) -> Word {
+        match *self {
+            IntcodeError::UnknownOpcode { instruction, .. }
+            | IntcodeError::InvalidParameterMode { instruction, .. }
+            | IntcodeError::NegativeAddress { instruction, .. }
+            | IntcodeError::AddressOutOfRange { instruction, .. }
+            | IntcodeError::ImmediateWrite { instruction, .. }
+            | IntcodeError::InputExhausted { instruction, .. }
+            | IntcodeError::LimitReached { instruction, .. }
+            | IntcodeError::Overflow { instruction, .. }
+            | IntcodeError::SecondWrite { instruction, .. } => instruction,
+        }
+    }
+}
+
+impl fmt::Display for IntcodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IntcodeError::UnknownOpcode { .. } => write!(f, "unknown opcode")?,
+            IntcodeError::InvalidParameterMode { mode, .. } => {
+                write!(f, "invalid parameter mode {}", mode)?
+            }
+            IntcodeError::NegativeAddress { address, .. } => {
+                write!(f, "negative address {}", address)?
+            }
+            IntcodeError::AddressOutOfRange { address, .. } => {
+                write!(f, "address {} out of range", address)?
+            }
+            IntcodeError::ImmediateWrite { .. } => write!(f, "write parameter in immediate mode")?,
+            IntcodeError::InputExhausted { .. } => write!(f, "input exhausted")?,
+            IntcodeError::LimitReached { limit, executed, .. } => {
+                write!(f, "{} reached after {} instructions", limit, executed)?
+            }
+            IntcodeError::Overflow { .. } => write!(f, "arithmetic overflow")?,
+            IntcodeError::SecondWrite { .. } => write!(f, "second memory write in one instruction")?,
+        }
+        write!(f, " at pc {} (instruction {})", self.pc(), self.instruction())
+    }
+}
+
+impl Error for IntcodeError {}
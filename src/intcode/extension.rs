@@ -0,0 +1,54 @@
+use std::fmt;
+
+use super::{Intcode, IntcodeError, OpcodeInfo, Word, PC};
+
+/// A parameter of a custom instruction after mode resolution: the value for
+/// read parameters, the address for write parameters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Param {
+    Value(Word),
+    Address(usize),
+}
+
+impl Param {
+    /// The resolved value, or the address for write parameters.
+    pub fn get(self) -> Word {
+        match self {
+            Param::Value(value) => value,
+            Param::Address(address) => address as Word,
+        }
+    }
+}
+
+/// What the VM does after a custom instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Effect {
+    /// Go on with the next instruction.
+    Continue,
+    Jump(PC),
+    /// Go on with the next instruction and hand the value to the caller like `OUT`.
+    Output(Word),
+}
+
+pub type Handler<I, O> = fn(&mut Intcode<I, O>, &[Param]) -> Result<Effect, IntcodeError>;
+
+/// A registered custom opcode.
+pub struct Extension<I, O> {
+    pub info: OpcodeInfo,
+    pub handler: Handler<I, O>,
+}
+
+impl<I, O> Clone for Extension<I, O> {
+    fn clone(&self) -> Self {
+        Extension {
+            info: self.info,
+            handler: self.handler,
+        }
+    }
+}
+
+impl<I, O> fmt::Debug for Extension<I, O> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Extension").field("info", &self.info).finish()
+    }
+}
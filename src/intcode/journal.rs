@@ -0,0 +1,62 @@
+use std::collections::VecDeque;
+
+use super::{MemoryWrite, Word, PC};
+
+/// What is needed to undo one executed instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JournalEntry {
+    pub pc: PC,
+    pub relative_base: Word,
+    pub write: Option<MemoryWrite>,
+    /// The value an input instruction consumed. It goes back to the front of
+    /// the pending input, so stepping forward again reads it once more.
+    pub input: Option<Word>,
+}
+
+/// Undo log of the most recent instructions, oldest entries are dropped once
+/// `capacity` is reached.
+#[derive(Debug, Clone)]
+pub struct Journal {
+    entries: VecDeque<JournalEntry>,
+    capacity: usize,
+}
+
+impl Journal {
+    pub fn new(capacity: usize) -> Self {
+        Journal {
+            entries: VecDeque::new(),
+            capacity,
+        }
+    }
+
+    pub fn push(&mut self, entry: JournalEntry) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+
+    pub fn pop(&mut self) -> Option<JournalEntry> {
+        self.entries.pop_back()
+    }
+
+    /// The entry that the next `pop` returns.
+    pub fn last(&self) -> Option<&JournalEntry> {
+        self.entries.back()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
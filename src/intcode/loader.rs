@@ -0,0 +1,146 @@
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use super::Word;
+
+type Unsigned = u128;
+
+/// Start of a binary program. The NUL keeps it from being mistaken for text.
+pub const BINARY_MAGIC: &[u8; 4] = b"\0ICB";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LoadError {
+    /// Token `index`, counted from 0 over the whole text, is not a number.
+    InvalidToken { index: usize, line: usize, text: String },
+    /// Word `index` of a binary program is cut off or does not fit a word.
+    InvalidWord { index: usize, offset: usize },
+    MissingMagic,
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::InvalidToken { index, line, text } => {
+                write!(f, "token {} on line {}: invalid number '{}'", index, line, text)
+            }
+            LoadError::InvalidWord { index, offset } => {
+                write!(f, "word {} at byte {}: truncated or too large", index, offset)
+            }
+            LoadError::MissingMagic => write!(f, "not a binary Intcode program"),
+        }
+    }
+}
+
+impl Error for LoadError {}
+
+/// Parses comma separated words. Line ends separate words too, `#` starts a
+/// comment and empty tokens, like after a trailing comma, are skipped.
+pub fn parse_program(text: &str) -> Result<Vec<Word>, LoadError> {
+    let mut program = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let code = line.split('#').next().unwrap_or("");
+        for token in code.split(',').map(str::trim).filter(|token| !token.is_empty()) {
+            let value = token.parse().map_err(|_| LoadError::InvalidToken {
+                index: program.len(),
+                line: number + 1,
+                text: token.to_string(),
+            })?;
+            program.push(value);
+        }
+    }
+    Ok(program)
+}
+
+/// Encodes a program as `BINARY_MAGIC` followed by one varint per word: the
+/// zigzag encoded word in groups of 7 bits, least significant group first,
+/// with the high bit set on every byte but the last.
+pub fn encode_binary(program: &[Word]) -> Vec<u8> {
+    let mut bytes = BINARY_MAGIC.to_vec();
+    for &value in program {
+        let mut zigzag = ((value << 1) ^ (value >> (Word::BITS - 1))) as Unsigned;
+        while zigzag >= 0x80 {
+            bytes.push(zigzag as u8 | 0x80);
+            zigzag >>= 7;
+        }
+        bytes.push(zigzag as u8);
+    }
+    bytes
+}
+
+pub fn decode_binary(bytes: &[u8]) -> Result<Vec<Word>, LoadError> {
+    let mut rest = bytes.strip_prefix(BINARY_MAGIC).ok_or(LoadError::MissingMagic)?;
+    let mut program = Vec::new();
+    while !rest.is_empty() {
+        let offset = bytes.len() - rest.len();
+        let error = LoadError::InvalidWord { index: program.len(), offset };
+        let mut zigzag: Unsigned = 0;
+        let mut shift = 0;
+        loop {
+            let (&byte, tail) = rest.split_first().ok_or_else(|| error.clone())?;
+            rest = tail;
+            let group = (byte & 0x7f) as Unsigned;
+            if shift >= Unsigned::BITS || (group << shift) >> shift != group {
+                return Err(error);
+            }
+            zigzag |= group << shift;
+            shift += 7;
+            if byte & 0x80 == 0 {
+                break;
+            }
+        }
+        program.push((zigzag >> 1) as Word ^ -((zigzag & 1) as Word));
+    }
+    Ok(program)
+}
+
+/// Loads a text or binary program, telling them apart by `BINARY_MAGIC`.
+pub fn load_program<P: AsRef<Path>>(path: P) -> io::Result<Vec<Word>> {
+    let bytes = fs::read(path)?;
+    let program = if bytes.starts_with(BINARY_MAGIC) {
+        decode_binary(&bytes)
+    } else {
+        let text = String::from_utf8(bytes).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+        parse_program(&text)
+    };
+    program.map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+}
+
+pub fn save_binary<P: AsRef<Path>>(path: P, program: &[Word]) -> io::Result<()> {
+    fs::write(path, encode_binary(program))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn binary_round_trip() {
+        let program = [Word::MIN, Word::MAX, 0, -1, 1, 1105, -64, 64];
+        let bytes = encode_binary(&program);
+        assert_eq!(encode_binary(&[0, -1, 1, -64])[BINARY_MAGIC.len()..], [0, 1, 2, 127]);
+        assert_eq!(decode_binary(&bytes), Ok(program.to_vec()));
+    }
+
+    #[test]
+    fn truncated_binary_is_an_error() {
+        let bytes = encode_binary(&[5, 300]);
+        let offset = bytes.len() - 2;
+        assert_eq!(
+            decode_binary(&bytes[..bytes.len() - 1]),
+            Err(LoadError::InvalidWord { index: 1, offset })
+        );
+        assert_eq!(decode_binary(b"1,2,3"), Err(LoadError::MissingMagic));
+    }
+
+    #[test]
+    fn text_with_comments_and_lines() {
+        let text = "# header\n1,0,0,3, # add\n99,\n\n  -5 ,7";
+        assert_eq!(parse_program(text), Ok(vec![1, 0, 0, 3, 99, -5, 7]));
+
+        let error = parse_program("1,2\n3,x4,5").unwrap_err();
+        assert_eq!(error, LoadError::InvalidToken { index: 3, line: 2, text: "x4".to_string() });
+    }
+}
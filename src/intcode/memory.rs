@@ -0,0 +1,118 @@
+use std::collections::BTreeMap;
+use std::ops::{Index, IndexMut};
+
+use super::Word;
+
+const PAGE_BITS: usize = 10;
+const PAGE_SIZE: usize = 1 << PAGE_BITS;
+
+static ZERO: Word = 0;
+
+/// Addresses at or above this are treated as runaway pointers by the VM.
+pub const ADDRESS_LIMIT: usize = 1 << 32;
+
+/// Sparse Intcode memory. Words are stored in fixed-size pages that are only
+/// allocated once written, every other address reads as zero.
+#[derive(Debug, Clone, Default)]
+pub struct Memory {
+    pages: BTreeMap<usize, Vec<Word>>,
+    len: usize,
+}
+
+impl Memory {
+    pub fn new() -> Self {
+        Memory::default()
+    }
+
+    pub fn get(&self, address: usize) -> Word {
+        self[address]
+    }
+
+    pub fn set(&mut self, address: usize, value: Word) {
+        self[address] = value;
+    }
+
+    /// One past the highest address that was loaded or written.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Raises `len` to at least `len` without writing anything.
+    pub fn grow_to(&mut self, len: usize) {
+        self.len = self.len.max(len);
+    }
+
+    /// Allocated words in address order, including zeros inside allocated pages.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, Word)> + '_ {
+        self.pages.iter().flat_map(|(page, words)| {
+            let base = page << PAGE_BITS;
+            words.iter().enumerate().map(move |(offset, word)| (base + offset, *word))
+        })
+    }
+
+    /// The words from address 0 up to `len`.
+    pub fn to_vec(&self) -> Vec<Word> {
+        (0..self.len).map(|address| self[address]).collect()
+    }
+}
+
+impl From<Vec<Word>> for Memory {
+    fn from(words: Vec<Word>) -> Self {
+        Memory::from(words.as_slice())
+    }
+}
+
+impl From<&[Word]> for Memory {
+    fn from(words: &[Word]) -> Self {
+        let mut memory = Memory::new();
+        for (page, chunk) in words.chunks(PAGE_SIZE).enumerate() {
+            let mut words = chunk.to_vec();
+            words.resize(PAGE_SIZE, 0);
+            memory.pages.insert(page, words);
+        }
+        memory.len = words.len();
+        memory
+    }
+}
+
+impl Index<usize> for Memory {
+    type Output = Word;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        match self.pages.get(&(index >> PAGE_BITS)) {
+            Some(words) => &words[index & (PAGE_SIZE - 1)],
+            None => &ZERO,
+        }
+    }
+}
+
+impl IndexMut<usize> for Memory {
+    fn index_mut(&mut self, index: usize) -> &mut Self::Output {
+        if index >= self.len {
+            self.len = index.saturating_add(1);
+        }
+        let words = self
+            .pages
+            .entry(index >> PAGE_BITS)
+            .or_insert_with(|| vec![0; PAGE_SIZE]);
+        &mut words[index & (PAGE_SIZE - 1)]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_grow_len_up_to_the_last_address() {
+        let mut memory = Memory::from(vec![1, 2, 3]);
+        memory[5000] = 7;
+        assert_eq!((memory.len(), memory[5000], memory[4000]), (5001, 7, 0));
+        memory[usize::MAX] = 5;
+        assert_eq!((memory.len(), memory[usize::MAX]), (usize::MAX, 5));
+    }
+}
@@ -0,0 +1,394 @@
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+use std::sync::Arc;
+use std::thread;
+
+use super::{IntcodeError, QueueIntcode, Status, Word};
+
+pub const NAT_ADDRESS: Word = 255;
+
+/// The NAT collects packets sent to `address` and, once the network has been
+/// idle for `idle_rounds` rounds, sends the last one to machine 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NatConfig {
+    pub address: Word,
+    /// Only used by `Network`. `run_threaded` has no rounds and wakes machine
+    /// 0 as soon as every machine is idle.
+    pub idle_rounds: usize,
+}
+
+impl Default for NatConfig {
+    fn default() -> Self {
+        NatConfig {
+            address: NAT_ADDRESS,
+            idle_rounds: 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Packet {
+    pub source: usize,
+    pub dest: Word,
+    pub x: Word,
+    pub y: Word,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    /// A machine sent a packet. Packets to unknown addresses are dropped.
+    Sent(Packet),
+    /// The network was idle and the NAT sent its last packet to machine 0.
+    Wake(Packet),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NetworkError {
+    Fault { address: usize, error: IntcodeError },
+    /// Nothing is going to happen anymore: the network is idle and the NAT has
+    /// nothing to send, or there is no NAT.
+    Idle,
+}
+
+impl fmt::Display for NetworkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NetworkError::Fault { address, error } => write!(f, "machine {}: {}", address, error),
+            NetworkError::Idle => write!(f, "network is idle with nothing left to send"),
+        }
+    }
+}
+
+impl Error for NetworkError {}
+
+/// Copies of one program, each booted with its own address, scheduled round
+/// robin: every round each machine runs until it waits for input again after
+/// reading -1 from an empty queue.
+pub struct Network {
+    machines: Vec<QueueIntcode>,
+    partial: Vec<Vec<Word>>,
+    nat: Option<NatConfig>,
+    nat_packet: Option<Packet>,
+    idle_rounds: usize,
+}
+
+impl Network {
+    pub fn new(program: &[Word], size: usize, nat: Option<NatConfig>) -> Self {
+        let machines = (0..size).map(|address| boot(program, address)).collect();
+        Network {
+            machines,
+            partial: vec![Vec::new(); size],
+            nat,
+            nat_packet: None,
+            idle_rounds: 0,
+        }
+    }
+
+    /// The last packet the NAT received.
+    pub fn nat_packet(&self) -> Option<Packet> {
+        self.nat_packet
+    }
+
+    /// Runs one round and returns what happened in it.
+    pub fn round(&mut self) -> Result<Vec<Event>, NetworkError> {
+        let mut events = Vec::new();
+        let mut idle = true;
+        for address in 0..self.machines.len() {
+            let machine = &mut self.machines[address];
+            if machine.is_halted() {
+                continue;
+            }
+            if !machine.source().is_empty() {
+                idle = false;
+            }
+            let mut sent = Vec::new();
+            let mut polled = false;
+            loop {
+                match machine.run().map_err(|error| NetworkError::Fault { address, error })? {
+                    Status::Output(value) => {
+                        let partial = &mut self.partial[address];
+                        partial.push(value);
+                        if partial.len() == 3 {
+                            sent.push(Packet {
+                                source: address,
+                                dest: partial[0],
+                                x: partial[1],
+                                y: partial[2],
+                            });
+                            partial.clear();
+                        }
+                    }
+                    Status::WaitingForInput if polled => break,
+                    Status::WaitingForInput => {
+                        polled = true;
+                        machine.source_mut().push_back(-1);
+                    }
+                    Status::Halted => break,
+                    Status::LimitReached { limit, executed, .. } => {
+                        let error = machine.limit_error(limit, executed);
+                        return Err(NetworkError::Fault { address, error });
+                    }
+                    Status::Running => unreachable!(),
+                }
+            }
+            for packet in sent {
+                idle = false;
+                events.push(Event::Sent(packet));
+                self.route(packet);
+            }
+        }
+
+        self.idle_rounds = if idle { self.idle_rounds + 1 } else { 0 };
+        if let Some(nat) = self.nat {
+            if self.idle_rounds >= nat.idle_rounds {
+                if let Some(packet) = self.nat_packet {
+                    self.machines[0].source_mut().extend(&[packet.x, packet.y]);
+                    self.idle_rounds = 0;
+                    events.push(Event::Wake(packet));
+                }
+            }
+        }
+        Ok(events)
+    }
+
+    /// Runs rounds until `stop` returns true for an event, and returns that event.
+    pub fn run_until<F: FnMut(&Event) -> bool>(&mut self, mut stop: F) -> Result<Event, NetworkError> {
+        let threshold = self.nat.map_or(1, |nat| nat.idle_rounds.max(1));
+        loop {
+            let events = self.round()?;
+            if let Some(event) = events.into_iter().find(|event| stop(event)) {
+                return Ok(event);
+            }
+            if self.idle_rounds >= threshold {
+                return Err(NetworkError::Idle);
+            }
+        }
+    }
+
+    fn route(&mut self, packet: Packet) {
+        if packet.dest >= 0 && (packet.dest as usize) < self.machines.len() {
+            self.machines[packet.dest as usize].source_mut().extend(&[packet.x, packet.y]);
+        } else if self.nat.is_some_and(|nat| nat.address == packet.dest) {
+            self.nat_packet = Some(packet);
+        }
+    }
+}
+
+fn boot(program: &[Word], address: usize) -> QueueIntcode {
+    QueueIntcode::with_io(program.to_vec(), VecDeque::from(vec![address as Word]), Vec::new())
+}
+
+enum Message {
+    Packet(Packet),
+    /// The machine found its queue empty twice in a row after having read
+    /// this many values from it.
+    Idle(usize, usize),
+    Halted(usize),
+    Fault(usize, IntcodeError),
+}
+
+/// Runs the same network with one thread per machine. Packets go through a
+/// router on the calling thread, which also runs the NAT and calls `stop`.
+/// The network is idle once every machine polled an empty queue twice after
+/// receiving everything sent to it; `NatConfig::idle_rounds` does not apply.
+pub fn run_threaded<F: FnMut(&Event) -> bool>(
+    program: &[Word],
+    size: usize,
+    nat: Option<NatConfig>,
+    mut stop: F,
+) -> Result<Event, NetworkError> {
+    let shutdown = Arc::new(AtomicBool::new(false));
+    let (router, messages) = channel();
+    let mut inputs = Vec::new();
+    let mut handles = Vec::new();
+    for address in 0..size {
+        let (input, queue) = channel();
+        inputs.push(input);
+        let machine = boot(program, address);
+        let router = router.clone();
+        let shutdown = Arc::clone(&shutdown);
+        handles.push(thread::spawn(move || run_machine(machine, address, queue, router, shutdown)));
+    }
+    drop(router);
+
+    let result = route_packets(&messages, &inputs, nat, &mut stop);
+    shutdown.store(true, Ordering::SeqCst);
+    for handle in handles {
+        handle.join().expect("Network machine thread panicked");
+    }
+    result
+}
+
+fn route_packets<F: FnMut(&Event) -> bool>(
+    messages: &Receiver<Message>,
+    inputs: &[Sender<Word>],
+    nat: Option<NatConfig>,
+    stop: &mut F,
+) -> Result<Event, NetworkError> {
+    let size = inputs.len();
+    let mut delivered = vec![0; size];
+    let mut idle_at: Vec<Option<usize>> = vec![None; size];
+    let mut halted = vec![false; size];
+    let mut nat_packet = None;
+    let deliver = |dest: usize, x: Word, y: Word, delivered: &mut [usize]| {
+        // A machine whose thread already stopped cannot receive anything anymore.
+        let _ = inputs[dest].send(x);
+        let _ = inputs[dest].send(y);
+        delivered[dest] += 2;
+    };
+    loop {
+        let message = match messages.recv() {
+            Ok(message) => message,
+            Err(_) => return Err(NetworkError::Idle),
+        };
+        match message {
+            Message::Packet(packet) => {
+                idle_at[packet.source] = None;
+                let event = Event::Sent(packet);
+                if stop(&event) {
+                    return Ok(event);
+                }
+                if packet.dest >= 0 && (packet.dest as usize) < size {
+                    deliver(packet.dest as usize, packet.x, packet.y, &mut delivered);
+                } else if nat.is_some_and(|nat| nat.address == packet.dest) {
+                    nat_packet = Some(packet);
+                }
+            }
+            Message::Idle(address, received) => idle_at[address] = Some(received),
+            Message::Halted(address) => halted[address] = true,
+            Message::Fault(address, error) => return Err(NetworkError::Fault { address, error }),
+        }
+        let idle = (0..size).all(|address| halted[address] || idle_at[address] == Some(delivered[address]));
+        if idle {
+            match (nat, nat_packet) {
+                (Some(_), Some(packet)) => {
+                    let event = Event::Wake(packet);
+                    if stop(&event) {
+                        return Ok(event);
+                    }
+                    deliver(0, packet.x, packet.y, &mut delivered);
+                }
+                _ => return Err(NetworkError::Idle),
+            }
+        }
+    }
+}
+
+fn run_machine(
+    mut machine: QueueIntcode,
+    address: usize,
+    queue: Receiver<Word>,
+    router: Sender<Message>,
+    shutdown: Arc<AtomicBool>,
+) {
+    let mut partial = Vec::new();
+    let mut received = 0;
+    let mut empty_polls = 0;
+    let mut reported = false;
+    while !shutdown.load(Ordering::SeqCst) {
+        let status = match machine.run() {
+            Ok(status) => status,
+            Err(error) => {
+                let _ = router.send(Message::Fault(address, error));
+                return;
+            }
+        };
+        match status {
+            Status::Output(value) => {
+                partial.push(value);
+                if partial.len() == 3 {
+                    let packet = Packet {
+                        source: address,
+                        dest: partial[0],
+                        x: partial[1],
+                        y: partial[2],
+                    };
+                    partial.clear();
+                    empty_polls = 0;
+                    reported = false;
+                    if router.send(Message::Packet(packet)).is_err() {
+                        return;
+                    }
+                }
+            }
+            Status::WaitingForInput => match queue.try_recv() {
+                Ok(value) => {
+                    received += 1;
+                    empty_polls = 0;
+                    reported = false;
+                    machine.feed(value);
+                }
+                Err(TryRecvError::Empty) => {
+                    empty_polls += 1;
+                    if empty_polls >= 2 && !reported {
+                        reported = true;
+                        if router.send(Message::Idle(address, received)).is_err() {
+                            return;
+                        }
+                    }
+                    machine.feed(-1);
+                    thread::yield_now();
+                }
+                Err(TryRecvError::Disconnected) => return,
+            },
+            Status::Halted => {
+                let _ = router.send(Message::Halted(address));
+                return;
+            }
+            Status::LimitReached { limit, executed, .. } => {
+                let _ = router.send(Message::Fault(address, machine.limit_error(limit, executed)));
+                return;
+            }
+            Status::Running => unreachable!(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::assembler::assemble;
+
+    /// Machine 0 sends 7, 9 to the NAT, then every machine reads packets forever.
+    const PROGRAM: &str = "
+                in -> [addr]
+                jt [addr], #poll
+                out #255
+                out #7
+                out #9
+        poll:   in -> [x]
+                eq [x], #-1 -> [empty]
+                jt [empty], #poll
+                in -> [y]
+                jf #0, #poll
+        addr:   data 0
+        x:      data 0
+        y:      data 0
+        empty:  data 0
+    ";
+
+    const WAKE: Event = Event::Wake(Packet { source: 0, dest: NAT_ADDRESS, x: 7, y: 9 });
+
+    fn is_wake(event: &Event) -> bool {
+        matches!(event, Event::Wake(_))
+    }
+
+    #[test]
+    fn round_robin_wakes_through_the_nat() {
+        let program = assemble(PROGRAM).unwrap();
+        let mut network = Network::new(&program, 50, Some(NatConfig::default()));
+        assert_eq!(network.run_until(is_wake), Ok(WAKE));
+        let mut network = Network::new(&program, 50, None);
+        assert_eq!(network.run_until(is_wake), Err(NetworkError::Idle));
+    }
+
+    #[test]
+    fn threaded_wakes_through_the_nat() {
+        let program = assemble(PROGRAM).unwrap();
+        assert_eq!(run_threaded(&program, 50, Some(NatConfig::default()), is_wake), Ok(WAKE));
+        assert_eq!(run_threaded(&program, 50, None, is_wake), Err(NetworkError::Idle));
+    }
+}
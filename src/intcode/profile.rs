@@ -0,0 +1,68 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use super::{opcode_info, OpcodeInfo, PC};
+
+/// Execution counts collected while profiling is enabled. Reads count data
+/// reads through position and relative parameters, not instruction fetches.
+#[derive(Debug, Clone, Default)]
+pub struct Profile {
+    pub retired: u64,
+    pub opcodes: HashMap<PC, u64>,
+    pub pcs: HashMap<PC, u64>,
+    pub reads: HashMap<usize, u64>,
+    pub writes: HashMap<usize, u64>,
+    /// Custom opcodes of the profiled machine, to name them in the report.
+    pub extensions: Vec<OpcodeInfo>,
+}
+
+impl Profile {
+    pub fn new() -> Self {
+        Profile::default()
+    }
+
+    /// Hot-spot report listing the `top` busiest entries of every table.
+    pub fn report(&self, top: usize) -> String {
+        let mut report = String::new();
+        writeln!(report, "Instructions retired: {}", self.retired).unwrap();
+
+        writeln!(report, "\nOpcodes:").unwrap();
+        for (opcode, count) in sorted(&self.opcodes, usize::MAX) {
+            let mnemonic = opcode_info(opcode)
+                .or_else(|| self.extensions.iter().find(|info| info.opcode == opcode))
+                .map_or("???", |info| info.mnemonic);
+            writeln!(report, "  {:>3} {:<4} {:>12} {:>6.2}%", opcode, mnemonic, count, self.percent(count)).unwrap();
+        }
+
+        writeln!(report, "\nHottest pcs:").unwrap();
+        for (pc, count) in sorted(&self.pcs, top) {
+            writeln!(report, "  {:04} {:>12} {:>6.2}%", pc, count, self.percent(count)).unwrap();
+        }
+
+        writeln!(report, "\nMost read addresses:").unwrap();
+        for (address, count) in sorted(&self.reads, top) {
+            writeln!(report, "  {:04} {:>12}", address, count).unwrap();
+        }
+
+        writeln!(report, "\nMost written addresses:").unwrap();
+        for (address, count) in sorted(&self.writes, top) {
+            writeln!(report, "  {:04} {:>12}", address, count).unwrap();
+        }
+        report
+    }
+
+    fn percent(&self, count: u64) -> f64 {
+        if self.retired == 0 {
+            return 0.0;
+        }
+        count as f64 * 100.0 / self.retired as f64
+    }
+}
+
+/// Busiest entries first, ties broken by key.
+fn sorted(counts: &HashMap<usize, u64>, top: usize) -> Vec<(usize, u64)> {
+    let mut entries: Vec<(usize, u64)> = counts.iter().map(|(key, count)| (*key, *count)).collect();
+    entries.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    entries.truncate(top);
+    entries
+}
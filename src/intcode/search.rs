@@ -0,0 +1,67 @@
+use std::ops::Range;
+use std::thread;
+
+use super::{QueueIntcode, Word};
+
+/// Which values to try at addresses 1 (noun) and 2 (verb), and what address 0
+/// has to hold afterwards.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchConfig {
+    pub nouns: Range<Word>,
+    pub verbs: Range<Word>,
+    pub target: Word,
+    pub threads: usize,
+}
+
+impl SearchConfig {
+    /// Nouns and verbs from 0 to 99, one thread per core.
+    pub fn new(target: Word) -> Self {
+        SearchConfig {
+            nouns: 0..100,
+            verbs: 0..100,
+            target,
+            threads: thread::available_parallelism().map_or(1, |threads| threads.get()),
+        }
+    }
+}
+
+/// Every `(noun, verb)` pair for which the program leaves `target` at address
+/// 0, ordered by noun and verb. Pairs that make the program fault or wait for
+/// input do not match. The nouns are split across threads, each working on
+/// its own clone of the machine.
+pub fn find_inputs(program: &[Word], config: &SearchConfig) -> Vec<(Word, Word)> {
+    let mut machine = QueueIntcode::with_io(program.to_vec(), Default::default(), Vec::new());
+    machine.enable_decode_cache();
+    let nouns: Vec<Word> = config.nouns.clone().collect();
+    let threads = config.threads.max(1);
+    let chunk = nouns.len().div_ceil(threads).max(1);
+
+    let mut matches: Vec<(Word, Word)> = thread::scope(|scope| {
+        let workers: Vec<_> = nouns
+            .chunks(chunk)
+            .map(|nouns| {
+                let mut machine = machine.clone();
+                scope.spawn(move || {
+                    let mut matches = Vec::new();
+                    for &noun in nouns {
+                        for verb in config.verbs.clone() {
+                            machine.reset();
+                            machine[1] = noun;
+                            machine[2] = verb;
+                            if machine.compute().is_ok() && machine.result() == config.target {
+                                matches.push((noun, verb));
+                            }
+                        }
+                    }
+                    matches
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().expect("Search thread panicked"))
+            .collect()
+    });
+    matches.sort_unstable();
+    matches
+}
@@ -0,0 +1,179 @@
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+use super::trace::Io;
+use super::{decode, InputSource, Intcode, IntcodeError, OutputSink, Status, Word};
+
+/// The values a program read and wrote during one run, in order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Session {
+    pub events: Vec<Io>,
+}
+
+impl Session {
+    pub fn inputs(&self) -> impl Iterator<Item = Word> + '_ {
+        self.events.iter().filter_map(|event| match event {
+            Io::Input(value) => Some(*value),
+            Io::Output(_) => None,
+        })
+    }
+
+    pub fn outputs(&self) -> impl Iterator<Item = Word> + '_ {
+        self.events.iter().filter_map(|event| match event {
+            Io::Output(value) => Some(*value),
+            Io::Input(_) => None,
+        })
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_string())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Session> {
+        fs::read_to_string(path)?
+            .parse()
+            .map_err(|message| io::Error::new(io::ErrorKind::InvalidData, message))
+    }
+}
+
+/// One event per line, `in 5` or `out 72`.
+impl fmt::Display for Session {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for event in &self.events {
+            match event {
+                Io::Input(value) => writeln!(f, "in {}", value)?,
+                Io::Output(value) => writeln!(f, "out {}", value)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Session {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut session = Session::default();
+        for line in text.lines().filter(|line| !line.trim().is_empty()) {
+            let invalid = || format!("Invalid session line '{}'", line);
+            let mut words = line.split_whitespace();
+            let key = words.next().ok_or_else(invalid)?;
+            let value = words.next().and_then(|value| value.parse().ok()).ok_or_else(invalid)?;
+            if words.next().is_some() {
+                return Err(invalid());
+            }
+            session.events.push(match key {
+                "in" => Io::Input(value),
+                "out" => Io::Output(value),
+                _ => return Err(invalid()),
+            });
+        }
+        Ok(session)
+    }
+}
+
+/// Runs the machine like `compute` and records every value it reads and
+/// writes. The session is returned even when the machine faults, so a run
+/// that ended with the input running out can still be replayed.
+pub fn record<I: InputSource, O: OutputSink>(machine: &mut Intcode<I, O>) -> (Session, Result<(), IntcodeError>) {
+    let mut session = Session::default();
+    loop {
+        let (opcode, _) = decode(machine[machine.pc()]);
+        let result = match machine.step() {
+            Ok(Status::Running) => {
+                if let Some(write) = machine.last_write().filter(|_| opcode == 3) {
+                    session.events.push(Io::Input(write.new));
+                }
+                continue;
+            }
+            Ok(Status::Output(value)) => {
+                session.events.push(Io::Output(value));
+                machine.sink_mut().write(value);
+                continue;
+            }
+            Ok(Status::Halted) => Ok(()),
+            Ok(Status::WaitingForInput) => Err(IntcodeError::InputExhausted {
+                pc: machine.pc(),
+                instruction: machine[machine.pc()],
+            }),
+            Ok(Status::LimitReached { limit, executed, .. }) => Err(machine.limit_error(limit, executed)),
+            Err(error) => Err(error),
+        };
+        return (session, result);
+    }
+}
+
+/// Where a replay first went differently from the recorded session.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Divergence {
+    /// Output number `index` differs. `expected` is `None` when the program
+    /// wrote more than was recorded, `actual` is `None` when it stopped early.
+    Output { index: usize, expected: Option<Word>, actual: Option<Word> },
+    Fault(IntcodeError),
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Divergence::Output { index, expected: Some(expected), actual: Some(actual) } => {
+                write!(f, "output {}: expected {}, got {}", index, expected, actual)
+            }
+            Divergence::Output { index, expected: Some(expected), actual: None } => {
+                write!(f, "output {}: expected {}, but the program stopped", index, expected)
+            }
+            Divergence::Output { index, actual: Some(actual), .. } => {
+                write!(f, "output {}: got {} after the recorded outputs ended", index, actual)
+            }
+            Divergence::Output { index, .. } => write!(f, "output {}", index),
+            Divergence::Fault(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl Error for Divergence {}
+
+/// Feeds the recorded inputs to the machine, ahead of its source, and checks
+/// its outputs against the recorded ones. Outputs still go to the sink.
+/// Running out of input after everything matched counts as success.
+pub fn replay<I: InputSource, O: OutputSink>(machine: &mut Intcode<I, O>, session: &Session) -> Result<(), Divergence> {
+    for value in session.inputs() {
+        machine.feed(value);
+    }
+    let mut expected = session.outputs();
+    let mut index = 0;
+    loop {
+        match machine.run().map_err(Divergence::Fault)? {
+            Status::Output(actual) => {
+                machine.sink_mut().write(actual);
+                match expected.next() {
+                    Some(value) if value == actual => index += 1,
+                    value => {
+                        return Err(Divergence::Output {
+                            index,
+                            expected: value,
+                            actual: Some(actual),
+                        })
+                    }
+                }
+            }
+            Status::Halted | Status::WaitingForInput => {
+                return match expected.next() {
+                    None => Ok(()),
+                    value => Err(Divergence::Output {
+                        index,
+                        expected: value,
+                        actual: None,
+                    }),
+                }
+            }
+            Status::LimitReached { limit, executed, .. } => {
+                return Err(Divergence::Fault(machine.limit_error(limit, executed)))
+            }
+            Status::Running => unreachable!(),
+        }
+    }
+}
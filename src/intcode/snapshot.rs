@@ -0,0 +1,156 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+use super::{Memory, Word, ADDRESS_LIMIT, PC};
+
+/// The complete state of a machine: registers, memory, input that was fed
+/// but not consumed yet and whatever the I/O devices hold, see
+/// `InputSource::queued` and `OutputSink::written`.
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    pub pc: PC,
+    pub relative_base: Word,
+    pub memory: Memory,
+    pub pending_input: Vec<Word>,
+    /// Values still queued in the input source.
+    pub source: Vec<Word>,
+    /// Values collected by the output sink.
+    pub sink: Vec<Word>,
+    pub executed: u64,
+}
+
+impl Snapshot {
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_string())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Snapshot> {
+        fs::read_to_string(path)?
+            .parse()
+            .map_err(|message| io::Error::new(io::ErrorKind::InvalidData, message))
+    }
+}
+
+/// Line-oriented text format. Memory is written as runs of non-zero words,
+/// everything else reads back as zero.
+///
+/// ```text
+/// pc 12
+/// rb 0
+/// input 1,2
+/// source 3
+/// sink 7,8
+/// executed 41
+/// len 678
+/// mem 0 3,225,1,225
+/// ```
+impl fmt::Display for Snapshot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "pc {}", self.pc)?;
+        writeln!(f, "rb {}", self.relative_base)?;
+        writeln!(f, "input {}", join(&self.pending_input))?;
+        writeln!(f, "source {}", join(&self.source))?;
+        writeln!(f, "sink {}", join(&self.sink))?;
+        writeln!(f, "executed {}", self.executed)?;
+        writeln!(f, "len {}", self.memory.len())?;
+        let mut run: Vec<Word> = Vec::new();
+        let mut start = 0;
+        for (address, value) in self.memory.iter() {
+            if value != 0 && !run.is_empty() && start + run.len() == address {
+                run.push(value);
+                continue;
+            }
+            if !run.is_empty() {
+                writeln!(f, "mem {} {}", start, join(&run))?;
+                run.clear();
+            }
+            if value != 0 {
+                start = address;
+                run.push(value);
+            }
+        }
+        if !run.is_empty() {
+            writeln!(f, "mem {} {}", start, join(&run))?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Snapshot {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut snapshot = Snapshot {
+            pc: 0,
+            relative_base: 0,
+            memory: Memory::new(),
+            pending_input: Vec::new(),
+            source: Vec::new(),
+            sink: Vec::new(),
+            executed: 0,
+        };
+        for (number, line) in text.lines().enumerate() {
+            let invalid = || format!("Invalid snapshot line {}: '{}'", number + 1, line);
+            let mut words = line.split_whitespace();
+            let key = match words.next() {
+                Some(key) => key,
+                None => continue,
+            };
+            let value = words.next().unwrap_or("");
+            match key {
+                "pc" => snapshot.pc = value.parse().map_err(|_| invalid())?,
+                "rb" => snapshot.relative_base = value.parse().map_err(|_| invalid())?,
+                "input" => snapshot.pending_input = split(value).ok_or_else(invalid)?,
+                "source" => snapshot.source = split(value).ok_or_else(invalid)?,
+                "sink" => snapshot.sink = split(value).ok_or_else(invalid)?,
+                "executed" => snapshot.executed = value.parse().map_err(|_| invalid())?,
+                "len" => {
+                    let len = value.parse().ok().filter(|len| *len <= ADDRESS_LIMIT).ok_or_else(invalid)?;
+                    snapshot.memory.grow_to(len);
+                }
+                "mem" => {
+                    let start: usize = value.parse().map_err(|_| invalid())?;
+                    let words = split(words.next().unwrap_or("")).ok_or_else(invalid)?;
+                    for (offset, word) in words.into_iter().enumerate() {
+                        let address = start
+                            .checked_add(offset)
+                            .filter(|address| *address < ADDRESS_LIMIT)
+                            .ok_or_else(invalid)?;
+                        snapshot.memory.set(address, word);
+                    }
+                }
+                _ => return Err(invalid()),
+            }
+        }
+        Ok(snapshot)
+    }
+}
+
+fn join(values: &[Word]) -> String {
+    values.iter().map(|value| value.to_string()).collect::<Vec<_>>().join(",")
+}
+
+fn split(text: &str) -> Option<Vec<Word>> {
+    if text.is_empty() {
+        return Some(Vec::new());
+    }
+    text.split(',').map(|value| value.parse().ok()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn addresses_past_the_limit_are_invalid() {
+        assert!("mem 18446744073709551615 1,2".parse::<Snapshot>().is_err());
+        assert!(format!("mem {} 1", ADDRESS_LIMIT).parse::<Snapshot>().is_err());
+        assert!(format!("len {}", ADDRESS_LIMIT + 1).parse::<Snapshot>().is_err());
+
+        let snapshot: Snapshot = "pc 2\nlen 10\nmem 4 7,8".parse().unwrap();
+        assert_eq!((snapshot.pc, snapshot.memory.len(), snapshot.memory[5]), (2, 10, 8));
+    }
+}
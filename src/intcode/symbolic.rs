@@ -0,0 +1,450 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::error::Error;
+use std::fmt;
+use std::ops::Range;
+
+use super::{decode, opcode_info, IntcodeError, ParameterMode, Role, Word, ADDRESS_LIMIT, PC};
+
+/// An affine expression: a constant plus a coefficient for each variable.
+/// Variables are named after the address they were placed at.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Expr {
+    terms: BTreeMap<usize, Word>,
+    constant: Word,
+}
+
+impl Expr {
+    pub fn constant(value: Word) -> Self {
+        Expr { terms: BTreeMap::new(), constant: value }
+    }
+
+    pub fn variable(address: usize) -> Self {
+        let mut terms = BTreeMap::new();
+        terms.insert(address, 1);
+        Expr { terms, constant: 0 }
+    }
+
+    /// The value of the expression if it does not depend on any variable.
+    pub fn as_constant(&self) -> Option<Word> {
+        if self.terms.is_empty() {
+            Some(self.constant)
+        } else {
+            None
+        }
+    }
+
+    pub fn constant_term(&self) -> Word {
+        self.constant
+    }
+
+    pub fn coefficient(&self, address: usize) -> Word {
+        self.terms.get(&address).copied().unwrap_or(0)
+    }
+
+    /// The variables the expression depends on, in address order.
+    pub fn variables(&self) -> impl Iterator<Item = usize> + '_ {
+        self.terms.keys().copied()
+    }
+
+    /// Evaluates the expression with the given variable values. Variables
+    /// without a value count as zero. `None` if the result does not fit a word.
+    pub fn evaluate(&self, values: &[(usize, Word)]) -> Option<Word> {
+        values.iter().try_fold(self.constant, |sum, (address, value)| {
+            sum.checked_add(self.coefficient(*address).checked_mul(*value)?)
+        })
+    }
+
+    /// Every assignment of values from `ranges` for which the expression equals
+    /// `target`, in the order of `ranges` and sorted. One variable is solved for
+    /// directly, so only the others are enumerated. `ranges` has to list every
+    /// variable of the expression.
+    pub fn solve(&self, target: Word, ranges: &[(usize, Range<Word>)]) -> Vec<Vec<Word>> {
+        for address in self.variables() {
+            assert!(
+                ranges.iter().any(|(listed, _)| *listed == address),
+                "No range given for variable [{}]",
+                address
+            );
+        }
+        let pivot = ranges.iter().rposition(|(address, _)| self.coefficient(*address) != 0);
+        let mut solutions = Vec::new();
+        let mut values = Vec::with_capacity(ranges.len());
+        self.solve_from(target, ranges, pivot, &mut values, &mut solutions);
+        solutions
+    }
+
+    fn solve_from(
+        &self,
+        target: Word,
+        ranges: &[(usize, Range<Word>)],
+        pivot: Option<usize>,
+        values: &mut Vec<Word>,
+        solutions: &mut Vec<Vec<Word>>,
+    ) {
+        let index = values.len();
+        if index == ranges.len() {
+            if self.sum(ranges, values) == Some(target) {
+                solutions.push(values.clone());
+            }
+            return;
+        }
+        let (address, range) = &ranges[index];
+        if pivot == Some(index) {
+            // Variables after the pivot have a zero coefficient.
+            let coefficient = self.coefficient(*address);
+            let solution = self
+                .sum(&ranges[..index], values)
+                .and_then(|sum| target.checked_sub(sum))
+                .filter(|remainder| remainder.checked_rem(coefficient) == Some(0))
+                .and_then(|remainder| remainder.checked_div(coefficient));
+            if let Some(value) = solution.filter(|value| range.contains(value)) {
+                values.push(value);
+                self.solve_from(target, ranges, pivot, values, solutions);
+                values.pop();
+            }
+            return;
+        }
+        for value in range.clone() {
+            values.push(value);
+            self.solve_from(target, ranges, pivot, values, solutions);
+            values.pop();
+        }
+    }
+
+    /// `None` on overflow, which no assignment can match.
+    fn sum(&self, ranges: &[(usize, Range<Word>)], values: &[Word]) -> Option<Word> {
+        ranges.iter().zip(values).try_fold(self.constant, |sum, ((address, _), value)| {
+            sum.checked_add(self.coefficient(*address).checked_mul(*value)?)
+        })
+    }
+
+    /// `None` if a coefficient or the constant overflows.
+    fn add(&self, other: &Expr) -> Option<Expr> {
+        let mut sum = self.clone();
+        sum.constant = sum.constant.checked_add(other.constant)?;
+        for (address, coefficient) in &other.terms {
+            let term = sum.terms.entry(*address).or_insert(0);
+            *term = term.checked_add(*coefficient)?;
+        }
+        sum.terms.retain(|_, coefficient| *coefficient != 0);
+        Some(sum)
+    }
+
+    /// `None` if a coefficient or the constant overflows.
+    fn scale(&self, factor: Word) -> Option<Expr> {
+        if factor == 0 {
+            return Some(Expr::constant(0));
+        }
+        let terms = self
+            .terms
+            .iter()
+            .map(|(address, coefficient)| Some((*address, coefficient.checked_mul(factor)?)))
+            .collect::<Option<_>>()?;
+        Some(Expr { terms, constant: self.constant.checked_mul(factor)? })
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut first = true;
+        for (address, coefficient) in &self.terms {
+            let sign = if *coefficient < 0 { "-" } else { "+" };
+            if first {
+                if *coefficient < 0 {
+                    write!(f, "-")?;
+                }
+            } else {
+                write!(f, " {} ", sign)?;
+            }
+            match coefficient.unsigned_abs() {
+                1 => write!(f, "[{}]", address)?,
+                factor => write!(f, "{}*[{}]", factor, address)?,
+            }
+            first = false;
+        }
+        match (first, self.constant) {
+            (true, constant) => write!(f, "{}", constant),
+            (false, 0) => Ok(()),
+            (false, constant) if constant < 0 => write!(f, " - {}", constant.unsigned_abs()),
+            (false, constant) => write!(f, " + {}", constant),
+        }
+    }
+}
+
+/// What a value that turned out not to be a plain number was needed for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Usage {
+    Instruction,
+    Address,
+    Condition,
+    JumpTarget,
+    Comparison,
+    RelativeBase,
+    Output,
+    Result,
+}
+
+impl fmt::Display for Usage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Usage::Instruction => "instruction word",
+            Usage::Address => "address",
+            Usage::Condition => "jump condition",
+            Usage::JumpTarget => "jump target",
+            Usage::Comparison => "comparison",
+            Usage::RelativeBase => "relative base adjustment",
+            Usage::Output => "output",
+            Usage::Result => "result",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SymbolicError {
+    Fault(IntcodeError),
+    /// A value that has to be known to go on depends on the variables.
+    Symbolic { pc: PC, usage: Usage, value: Expr },
+    /// A value is not affine in the variables, because two variable values
+    /// were multiplied or a read went through a variable address at `origin`.
+    NotAffine { pc: PC, usage: Usage, origin: PC },
+}
+
+impl fmt::Display for SymbolicError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SymbolicError::Fault(error) => write!(f, "{}", error),
+            SymbolicError::Symbolic { pc, usage, value } => {
+                write!(f, "{} depends on the variables ({}) at pc {}", usage, value, pc)
+            }
+            SymbolicError::NotAffine { pc, usage, origin } => write!(
+                f,
+                "{} at pc {} is not affine in the variables (computed at pc {})",
+                usage, pc, origin
+            ),
+        }
+    }
+}
+
+impl Error for SymbolicError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Value {
+    Affine(Expr),
+    /// Computed at the given pc, but not expressible as an `Expr`. Harmless
+    /// unless something needs it.
+    Opaque(PC),
+}
+
+/// Runs a program with some addresses replaced by variables, tracking every
+/// memory word as an affine expression of them. Execution stops with an error
+/// as soon as control flow, an address or the code itself depends on a variable.
+#[derive(Debug, Clone)]
+pub struct SymbolicIntcode {
+    pc: PC,
+    relative_base: Word,
+    memory: BTreeMap<usize, Value>,
+    input: VecDeque<Word>,
+    outputs: Vec<Expr>,
+    halted: bool,
+}
+
+impl SymbolicIntcode {
+    pub fn new(program: &[Word], variables: &[usize]) -> Self {
+        let mut memory: BTreeMap<usize, Value> = program
+            .iter()
+            .enumerate()
+            .filter(|(_, value)| **value != 0)
+            .map(|(address, value)| (address, Value::Affine(Expr::constant(*value))))
+            .collect();
+        for &address in variables {
+            memory.insert(address, Value::Affine(Expr::variable(address)));
+        }
+        SymbolicIntcode {
+            pc: 0,
+            relative_base: 0,
+            memory,
+            input: VecDeque::new(),
+            outputs: Vec::new(),
+            halted: false,
+        }
+    }
+
+    /// Queues a concrete value for the next input instruction.
+    pub fn feed(&mut self, value: Word) {
+        self.input.push_back(value);
+    }
+
+    pub fn pc(&self) -> PC {
+        self.pc
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    pub fn outputs(&self) -> &[Expr] {
+        &self.outputs
+    }
+
+    /// The expression stored at `address`.
+    pub fn get(&self, address: usize) -> Result<Expr, SymbolicError> {
+        self.expr(&self.load(address), Usage::Result)
+    }
+
+    /// The expression stored at address 0.
+    pub fn result(&self) -> Result<Expr, SymbolicError> {
+        self.get(0)
+    }
+
+    /// Runs until the program halts.
+    pub fn run(&mut self) -> Result<(), SymbolicError> {
+        while !self.halted {
+            self.step()?;
+        }
+        Ok(())
+    }
+
+    pub fn step(&mut self) -> Result<(), SymbolicError> {
+        if self.halted {
+            return Ok(());
+        }
+        let pc = self.pc;
+        let instruction = self.concrete(&self.load(pc), Usage::Instruction)?;
+        let (opcode, mut mode_iter) = decode(instruction);
+        let overflow = || SymbolicError::Fault(IntcodeError::Overflow { pc, instruction });
+        let info = opcode_info(opcode)
+            .ok_or(SymbolicError::Fault(IntcodeError::UnknownOpcode { pc, instruction }))?;
+
+        let mut reads = Vec::new();
+        let mut write = None;
+        for (i, role) in info.params.iter().enumerate() {
+            let mode = mode_iter.next().map_err(|mode| {
+                SymbolicError::Fault(IntcodeError::InvalidParameterMode { pc, instruction, mode })
+            })?;
+            let operand = self.load(pc + 1 + i);
+            if mode == ParameterMode::Immediate {
+                if *role == Role::Write {
+                    return Err(SymbolicError::Fault(IntcodeError::ImmediateWrite { pc, instruction }));
+                }
+                reads.push(operand);
+                continue;
+            }
+            let offset = match role {
+                Role::Read => match self.concrete(&operand, Usage::Address) {
+                    Ok(offset) => offset,
+                    // Which word gets read is unknown, but its value may never matter.
+                    Err(_) => {
+                        reads.push(Value::Opaque(pc));
+                        continue;
+                    }
+                },
+                Role::Write => self.concrete(&operand, Usage::Address)?,
+            };
+            let base = if mode == ParameterMode::Relative { self.relative_base } else { 0 };
+            let address = self.to_address(instruction, base.checked_add(offset).ok_or_else(overflow)?)?;
+            match role {
+                Role::Read => reads.push(self.load(address)),
+                Role::Write => write = Some(address),
+            }
+        }
+
+        let mut next = pc + 1 + info.params.len();
+        let value = match opcode {
+            1 => Some(match (&reads[0], &reads[1]) {
+                (Value::Affine(a), Value::Affine(b)) => Value::Affine(a.add(b).ok_or_else(overflow)?),
+                _ => Value::Opaque(pc),
+            }),
+            2 => Some(match (&reads[0], &reads[1]) {
+                (Value::Affine(a), Value::Affine(b)) => match (a.as_constant(), b.as_constant()) {
+                    (Some(factor), _) => Value::Affine(b.scale(factor).ok_or_else(overflow)?),
+                    (_, Some(factor)) => Value::Affine(a.scale(factor).ok_or_else(overflow)?),
+                    _ => Value::Opaque(pc),
+                },
+                _ => Value::Opaque(pc),
+            }),
+            3 => match self.input.pop_front() {
+                Some(value) => Some(Value::Affine(Expr::constant(value))),
+                None => return Err(SymbolicError::Fault(IntcodeError::InputExhausted { pc, instruction })),
+            },
+            4 => {
+                let value = self.expr(&reads[0], Usage::Output)?;
+                self.outputs.push(value);
+                None
+            }
+            5 | 6 => {
+                let condition = self.concrete(&reads[0], Usage::Condition)?;
+                if (condition != 0) == (opcode == 5) {
+                    let target = self.concrete(&reads[1], Usage::JumpTarget)?;
+                    next = self.to_address(instruction, target)?;
+                }
+                None
+            }
+            7 | 8 => {
+                let a = self.concrete(&reads[0], Usage::Comparison)?;
+                let b = self.concrete(&reads[1], Usage::Comparison)?;
+                let holds = if opcode == 7 { a < b } else { a == b };
+                Some(Value::Affine(Expr::constant(holds as Word)))
+            }
+            9 => {
+                let adjustment = self.concrete(&reads[0], Usage::RelativeBase)?;
+                self.relative_base = self.relative_base.checked_add(adjustment).ok_or_else(overflow)?;
+                None
+            }
+            _ => {
+                self.halted = true;
+                return Ok(());
+            }
+        };
+        if let (Some(address), Some(value)) = (write, value) {
+            self.memory.insert(address, value);
+        }
+        self.pc = next;
+        Ok(())
+    }
+
+    fn load(&self, address: usize) -> Value {
+        self.memory.get(&address).cloned().unwrap_or(Value::Affine(Expr::constant(0)))
+    }
+
+    fn to_address(&self, instruction: Word, address: Word) -> Result<usize, SymbolicError> {
+        let pc = self.pc;
+        if address < 0 {
+            Err(SymbolicError::Fault(IntcodeError::NegativeAddress { pc, instruction, address }))
+        } else if address >= ADDRESS_LIMIT as Word {
+            Err(SymbolicError::Fault(IntcodeError::AddressOutOfRange { pc, instruction, address }))
+        } else {
+            Ok(address as usize)
+        }
+    }
+
+    fn expr(&self, value: &Value, usage: Usage) -> Result<Expr, SymbolicError> {
+        match value {
+            Value::Affine(expr) => Ok(expr.clone()),
+            Value::Opaque(origin) => Err(SymbolicError::NotAffine { pc: self.pc, usage, origin: *origin }),
+        }
+    }
+
+    fn concrete(&self, value: &Value, usage: Usage) -> Result<Word, SymbolicError> {
+        let expr = self.expr(value, usage)?;
+        expr.as_constant().ok_or(SymbolicError::Symbolic { pc: self.pc, usage, value: expr })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overflow_is_a_fault() {
+        let program = [1101, Word::MAX, 1, 5, 99, 0];
+        let mut machine = SymbolicIntcode::new(&program, &[]);
+        let fault = IntcodeError::Overflow { pc: 0, instruction: 1101 };
+        assert_eq!(machine.run(), Err(SymbolicError::Fault(fault)));
+
+        let program = [1002, 5, 2, 0, 99, 0];
+        let mut machine = SymbolicIntcode::new(&program, &[5]);
+        machine.run().unwrap();
+        assert_eq!(machine.result().unwrap().evaluate(&[(5, Word::MAX)]), None);
+    }
+}
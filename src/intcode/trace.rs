@@ -0,0 +1,117 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use super::{MemoryWrite, Word, PC};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Io {
+    Input(Word),
+    Output(Word),
+}
+
+/// One executed instruction. `params` holds the resolved value of every read
+/// parameter and the resolved address of every write parameter.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceRecord {
+    pub pc: PC,
+    pub opcode: PC,
+    pub params: Vec<Word>,
+    pub write: Option<MemoryWrite>,
+    pub io: Option<Io>,
+}
+
+/// Formats as a single line, e.g. `pc=2 op=1 params=5,3,7 write=7:0:8`.
+impl fmt::Display for TraceRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let params: Vec<String> = self.params.iter().map(|param| param.to_string()).collect();
+        write!(f, "pc={} op={} params={}", self.pc, self.opcode, params.join(","))?;
+        if let Some(write) = self.write {
+            write!(f, " write={}:{}:{}", write.address, write.old, write.new)?;
+        }
+        match self.io {
+            Some(Io::Input(value)) => write!(f, " in={}", value),
+            Some(Io::Output(value)) => write!(f, " out={}", value),
+            None => Ok(()),
+        }
+    }
+}
+
+impl FromStr for TraceRecord {
+    type Err = String;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid trace line '{}'", line);
+        let number = |text: &str| text.parse::<Word>().map_err(|_| invalid());
+        let mut record = TraceRecord {
+            pc: 0,
+            opcode: 0,
+            params: Vec::new(),
+            write: None,
+            io: None,
+        };
+        for field in line.split_whitespace() {
+            let mut parts = field.splitn(2, '=');
+            let key = parts.next().ok_or_else(invalid)?;
+            let value = parts.next().ok_or_else(invalid)?;
+            match key {
+                "pc" => record.pc = value.parse().map_err(|_| invalid())?,
+                "op" => record.opcode = value.parse().map_err(|_| invalid())?,
+                "params" if value.is_empty() => {}
+                "params" => {
+                    record.params = value.split(',').map(number).collect::<Result<_, _>>()?;
+                }
+                "write" => {
+                    let parts: Vec<&str> = value.split(':').collect();
+                    if parts.len() != 3 {
+                        return Err(invalid());
+                    }
+                    record.write = Some(MemoryWrite {
+                        address: parts[0].parse().map_err(|_| invalid())?,
+                        old: number(parts[1])?,
+                        new: number(parts[2])?,
+                    });
+                }
+                "in" => record.io = Some(Io::Input(number(value)?)),
+                "out" => record.io = Some(Io::Output(number(value)?)),
+                _ => return Err(invalid()),
+            }
+        }
+        Ok(record)
+    }
+}
+
+pub fn write_trace<P: AsRef<Path>>(path: P, records: &[TraceRecord]) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    for record in records {
+        writeln!(writer, "{}", record)?;
+    }
+    writer.flush()
+}
+
+pub fn read_trace<P: AsRef<Path>>(path: P) -> io::Result<Vec<TraceRecord>> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut records = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record = line
+            .parse()
+            .map_err(|message| io::Error::new(io::ErrorKind::InvalidData, message))?;
+        records.push(record);
+    }
+    Ok(records)
+}
+
+/// Index of the first record where two traces differ, if any.
+pub fn first_divergence(a: &[TraceRecord], b: &[TraceRecord]) -> Option<usize> {
+    match a.iter().zip(b).position(|(a, b)| a != b) {
+        Some(index) => Some(index),
+        None if a.len() != b.len() => Some(a.len().min(b.len())),
+        None => None,
+    }
+}
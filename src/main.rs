@@ -1,25 +1,179 @@
 mod input;
 // mod one;
-// mod two;
+mod two;
 // mod three;
 // mod four;
-// mod five;
+mod five;
 mod six;
 
 mod intcode;
 
+use std::collections::VecDeque;
+use std::env;
+use std::fs;
+use std::time::Instant;
+
 use input::Input;
+use intcode::ascii::AsciiConsole;
+use intcode::cfg::ControlFlowGraph;
+use intcode::debugger::Debugger;
+use intcode::dump;
+use intcode::loader;
+use intcode::session::{self, Session};
+use intcode::symbolic::SymbolicIntcode;
+use intcode::trace;
+use intcode::{Console, InputSource, Intcode, OutputSink, Word};
 
 fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() == 3 && args[1] == "debug" {
+        let machine = Intcode::with_io(read_program(&args[2]), VecDeque::new(), Console);
+        Debugger::new(machine).repl();
+        return;
+    }
+    if args.len() == 4 && args[1] == "trace" {
+        let mut machine = Intcode::new(read_program(&args[2]));
+        machine.enable_trace();
+        if let Err(error) = machine.compute() {
+            println!("{}", error);
+        }
+        trace::write_trace(&args[3], &machine.disable_trace()).expect("Failed to write trace");
+        return;
+    }
+    if args.len() == 4 && args[1] == "cfg" {
+        let graph = ControlFlowGraph::build(&read_program(&args[2]));
+        fs::write(&args[3], graph.to_dot()).expect("Failed to write graph");
+        println!("{} basic blocks", graph.blocks.len());
+        for flag in &graph.flags {
+            println!("{}", flag);
+        }
+        return;
+    }
+    if args.len() == 3 && args[1] == "diff" {
+        let mut machine = Intcode::new(read_program(&args[2]));
+        if let Err(error) = machine.compute() {
+            println!("{}", error);
+        }
+        print!("{}", dump::format_diff(machine.original_memory(), &machine.memory_diff()));
+        return;
+    }
+    if args.len() == 3 && args[1] == "profile" {
+        let mut machine = Intcode::new(read_program(&args[2]));
+        machine.enable_profile();
+        if let Err(error) = machine.compute() {
+            println!("{}", error);
+        }
+        print!("{}", machine.disable_profile().report(10));
+        return;
+    }
+    if (args.len() == 3 || args.len() == 4) && args[1] == "play" {
+        let mut machine = Intcode::with_io(read_program(&args[2]), AsciiConsole::new(), AsciiConsole::new());
+        match args.get(3) {
+            Some(path) => record_session(&mut machine, path),
+            None => {
+                if let Err(error) = machine.compute() {
+                    println!("{}", error);
+                }
+            }
+        }
+        return;
+    }
+    if args.len() == 4 && args[1] == "record" {
+        record_session(&mut Intcode::new(read_program(&args[2])), &args[3]);
+        return;
+    }
+    if args.len() == 4 && args[1] == "replay" {
+        let session = Session::load(&args[3]).expect("Failed to read session");
+        let mut machine = Intcode::with_io(read_program(&args[2]), VecDeque::new(), Vec::new());
+        match session::replay(&mut machine, &session) {
+            Ok(()) => println!("Replay matches all {} outputs", session.outputs().count()),
+            Err(divergence) => println!("Replay diverged at {}", divergence),
+        }
+        return;
+    }
+    if args.len() == 4 && args[1] == "solve" {
+        let target = args[3].parse().expect("Failed to parse target");
+        solve(&read_program(&args[2]), target);
+        return;
+    }
+    if args.len() == 4 && args[1] == "pack" {
+        loader::save_binary(&args[3], &read_program(&args[2])).expect("Failed to write program");
+        return;
+    }
+    if args.len() == 3 && args[1] == "bench" {
+        bench(read_program(&args[2]));
+        return;
+    }
+    if args.len() == 4 && args[1] == "day" {
+        let f = Input::new(&args[3]);
+        match args[2].as_str() {
+            "2" => two::run(f.as_string()),
+            "5" => five::run(f.as_string()),
+            "6" => six::run(f.as_strings()),
+            day => println!("No solution for day {}", day),
+        }
+        return;
+    }
+
     // let f = Input::new("input_1");
     // one::run(f.as_i64());
-    // let f = Input::new("input_2");
-    // two::run(f.as_string());
     // let f = Input::new("input_3");
     // three::run(f.as_strings());
     // four::run(246515,739105);
-    // let f = Input::new("input_5");
-    // five::run(f.as_string());
     let f = Input::new("input_6");
     six::run(f.as_strings());
 }
+
+fn read_program(filename: &str) -> Vec<Word> {
+    loader::load_program(filename).expect("Failed to load Intcode program")
+}
+
+/// Runs the machine while recording its session to `path`.
+fn record_session<I: InputSource, O: OutputSink>(machine: &mut Intcode<I, O>, path: &str) {
+    let (session, result) = session::record(machine);
+    if let Err(error) = result {
+        println!("{}", error);
+    }
+    session.save(path).expect("Failed to write session");
+}
+
+/// Times the full day 2 noun/verb search, without and with the decode cache.
+fn bench(program: Vec<Word>) {
+    for &cached in &[false, true] {
+        let mut computer = Intcode::new(program.clone());
+        if cached {
+            computer.enable_decode_cache();
+        }
+        let start = Instant::now();
+        let mut matches = 0;
+        for noun in 0..100 {
+            for verb in 0..100 {
+                computer.reset();
+                computer[1] = noun;
+                computer[2] = verb;
+                computer.compute().expect("Intcode program faulted");
+                if computer.result() == 19690720 {
+                    matches += 1;
+                }
+            }
+        }
+        let name = if cached { "Decode cache" } else { "Interpreter" };
+        println!("{}: {:?} for 10000 runs, {} matches", name, start.elapsed(), matches);
+    }
+}
+
+/// Solves a day 2 style program for the noun and verb that give `target`.
+fn solve(program: &[Word], target: Word) {
+    let mut machine = SymbolicIntcode::new(program, &[1, 2]);
+    let result = match machine.run().and_then(|_| machine.result()) {
+        Ok(result) => result,
+        Err(error) => {
+            println!("{}", error);
+            return;
+        }
+    };
+    println!("[0] = {}", result);
+    for solution in result.solve(target, &[(1, 0..100), (2, 0..100)]) {
+        println!("Noun: {} Verb: {} Result: {}", solution[0], solution[1], 100 * solution[0] + solution[1]);
+    }
+}
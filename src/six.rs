@@ -46,7 +46,7 @@ impl Galaxy {
             self.objects.push(object);
         }
         if let Some(result) = self.find_object(object_name) {
-            return result;
+            result
         } else {
             panic!("Failed to fetch object");
         }
@@ -59,7 +59,7 @@ impl Galaxy {
     fn find_object(&mut self, object_name: &str) -> Option<SharedSpaceObject> {
         for object in &self.objects {
             if object.borrow().name == object_name {
-                return Some(Rc::clone(&object));
+                return Some(Rc::clone(object));
             }
         }
         None
@@ -138,7 +138,7 @@ pub fn run(input: Vec<String>) {
         let object = object_rc.borrow();
         for child in &object.children {
             child.borrow_mut().orbit_count = object.orbit_count + 1;
-            objects_to_handle.push(Rc::clone(&child));
+            objects_to_handle.push(Rc::clone(child));
         }
     }
     let count: u64 = galaxy.objects.iter().map(|x| x.borrow().orbit_count).sum();
@@ -1,36 +1,24 @@
+use crate::intcode::loader::parse_program;
+use crate::intcode::search::{find_inputs, SearchConfig};
 use crate::intcode::Intcode;
 
 pub fn run(input: String) {
-    let input = input.trim();
-    let input: Vec<&str> = input.split(",").collect();
-    let mut memory = Vec::new();
-    for value in input {
-        let num : i64 = value.parse().expect("Failed to parse usize for Day 2");
-        memory.push(num);
-    }
+    let memory = parse_program(&input).expect("Failed to parse Intcode program");
 
-    let mut computer = Intcode::new(memory);
+    let mut computer = Intcode::new(memory.clone());
 
     //Part 1
     computer[1] = 12;
     computer[2] = 2;
-    computer.compute();
+    computer.compute().expect("Intcode program faulted");
     println!("Day2 Part 1: {:?}", computer.result());
     
     //Part 2
-    for noun in 0..100 {
-        for verb in 0..100 {
-            computer.reset();
-            computer[1] = noun;
-            computer[2] = verb;
-            computer.compute();
-            if computer.result() == 19690720 {
-                println!("Day2 Part2: Noun: {} Verb: {} Result: {}", noun, verb, 100*noun + verb);
-                return;
-            }
-        }
+    let matches = find_inputs(&memory, &SearchConfig::new(19690720));
+    for (noun, verb) in &matches {
+        println!("Day2 Part2: Noun: {} Verb: {} Result: {}", noun, verb, 100*noun + verb);
+    }
+    if matches.is_empty() {
+        println!("Day2 Part2: No Verb and noun found");
     }
-    println!("Day2 Part1: No Verb and noun found");
-            
 }
-